@@ -14,6 +14,8 @@ pub mod module {
     use std::fs::OpenOptions;
     use std::path::Path;
     use std::path::PathBuf;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[gen_struct_sugar(defaults(name = r#""Bob".to_owned()"#))]
     pub fn is_a_test(name: String, message: String) -> String {
@@ -42,10 +44,23 @@ pub mod module {
             create_new = "false"
         ),
         positionals(path),
+        docs(
+            append = "Append to the end of the file when writing",
+            truncate = "Truncate the file to zero length when opening it",
+            create = "Create the file if it doesn't exist",
+            create_new = "Create the file, failing if it already exists",
+        ),
+        exclusive(append, truncate),
+        requires(create_new = "write"),
+        into(path),
+        map_err = "|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err)",
     )]
     pub fn open_file(
+        /// The path of the file to open
         path: PathBuf,
+        /// Open the file for reading
         read: bool,
+        /// Open the file for writing
         write: bool,
         append: bool,
         truncate: bool,
@@ -61,6 +76,484 @@ pub mod module {
             .create_new(create_new)
             .open(path)
     }
+
+    #[gen_struct_sugar(
+        defaults(greeting = r#""Hello".to_owned()"#),
+        positionals(name),
+        args_name = "GreetArgs",
+        builder_name = "GreetBuilder",
+        macro_name = "greet_named",
+    )]
+    pub fn greet(name: String, greeting: String) -> String {
+        format!("{}, {}!", &greeting, &name)
+    }
+
+    #[gen_struct_sugar(
+        defaults(retries = "3"),
+        args_name = "FetchArgs",
+        on_struct(derive(Debug, Clone, PartialEq)),
+        on_fields(url, doc = "The url to fetch"),
+    )]
+    #[for_field(retries, doc = "How many times to retry")]
+    pub fn fetch(url: String, retries: u32) -> String {
+        format!("Fetching {} with {} retries", &url, retries)
+    }
+
+    #[gen_struct_sugar(
+        defaults(path = r#""".to_owned()"#, fd = "-1"),
+        one_of(path, fd),
+    )]
+    pub fn describe_target(path: String, fd: i32) -> Result<String, ArgError> {
+        if fd >= 0 {
+            Ok(format!("File descriptor {}", fd))
+        } else {
+            Ok(format!("Path {}", &path))
+        }
+    }
+
+    #[gen_struct_sugar(
+        defaults(verbose = env("EXAMPLE_SYNC_VERBOSE", true), dry_run = "false", label = r#""sync".to_owned()"#),
+        requires(dry_run = "verbose"),
+        exclusive(dry_run, label),
+    )]
+    pub fn sync(verbose: bool, dry_run: bool, label: String) -> Result<String, ArgError> {
+        Ok(format!("{} (verbose: {}, dry run: {})", label, verbose, dry_run))
+    }
+
+    fn parse_port(raw: &str) -> u16 {
+        raw.parse().expect("Not a valid port")
+    }
+
+    #[gen_struct_sugar(
+        defaults(scheme = r#""http".to_owned()"#, port = "8080", user = r#""anonymous".to_owned()"#),
+        positionals(host),
+        into(host, user),
+        with(scheme = "|raw: &str| raw.to_lowercase()", port = "parse_port: &str"),
+    )]
+    pub fn connect(host: String, scheme: String, port: u16, user: String) -> String {
+        format!("{}://{}@{}:{}", &scheme, &user, &host, port)
+    }
+
+    #[gen_struct_sugar(
+        defaults(port = "8080"),
+        positionals(host),
+        try_into(port),
+        validate(host = "!host.is_empty()", port = "port > 0"),
+    )]
+    pub fn listen(host: String, port: u16) -> Result<String, ArgError> {
+        Ok(format!("Listening on {}:{}", &host, port))
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum PoolError {
+        BadArgument(ArgError),
+    }
+
+    #[gen_struct_sugar(
+        defaults(workers = "4"),
+        validate(workers = "workers <= 64"),
+        map_err = "PoolError::BadArgument",
+    )]
+    pub fn spawn_pool(workers: u32) -> Result<u32, PoolError> {
+        Ok(workers)
+    }
+
+    pub type Label = Option<String>;
+
+    #[gen_struct_sugar(
+        defaults(limit = "Some(10)", label = r#"Some("all".to_owned())"#),
+        nullable(label),
+    )]
+    pub fn list_items(limit: std::option::Option<u32>, label: Label) -> String {
+        let limit = limit.map_or("unlimited".to_owned(), |v| v.to_string());
+        format!("{} items ({})", limit, label.unwrap_or_else(|| "unlabelled".to_owned()))
+    }
+
+    #[gen_struct_sugar(
+        defaults(timeout = default, retries = "3"),
+        optional(tag),
+        positionals(job),
+        into(job),
+    )]
+    pub fn schedule(job: String, timeout: u64, retries: u32, tag: Option<String>) -> String {
+        let timeout = if timeout == 0 { "no timeout".to_owned() } else { format!("{}s", timeout) };
+        match tag {
+            Some(tag) => format!("{} [{}] ({}, {} retries)", &job, &tag, &timeout, retries),
+            None => format!("{} ({}, {} retries)", &job, &timeout, retries),
+        }
+    }
+
+    #[gen_struct_sugar(
+        defaults(
+            host = r#""localhost".to_owned()"#,
+            port = env("EXAMPLE_API_PORT", 8080),
+            workers = "1",
+            name = env("EXAMPLE_API_NAME"),
+        ),
+        defaults_from_file = "config/app.toml",
+    )]
+    pub fn serve(host: String, port: u16, workers: u32, name: String) -> Result<String, ArgError> {
+        Ok(format!("{} on {}:{} with {} workers", &name, &host, port, workers))
+    }
+
+    #[gen_struct_sugar(
+        defaults(timeout = "30", retries = "3"),
+        positionals(url),
+        overridable(timeout, retries),
+    )]
+    pub fn download(url: &'static str, timeout: u64, retries: u32) -> String {
+        format!("Downloading {} ({}s timeout, {} retries)", url, timeout, retries)
+    }
+
+    #[gen_struct_sugar(
+        defaults(timeout = "30", retries = "3"),
+        defaults_cfg(test, timeout = "1"),
+        defaults_cfg(feature = "nightly", timeout = "10", retries = "0"),
+    )]
+    pub fn poll(timeout: u64, retries: u32) -> String {
+        format!("Polling every {}s, {} retries", timeout, retries)
+    }
+
+    /// How many times the stop words were loaded
+    pub static STOP_WORDS_LOADED: AtomicUsize = AtomicUsize::new(0);
+
+    pub fn load_stop_words() -> HashSet<&'static str> {
+        STOP_WORDS_LOADED.fetch_add(1, Ordering::SeqCst);
+        ["a", "an", "the"].iter().cloned().collect()
+    }
+
+    #[gen_struct_sugar(
+        defaults(separators = once "vec![' ', ',']", stop_words = once "load_stop_words()"),
+        positionals(text),
+    )]
+    pub fn tokenize(text: &'static str, separators: Vec<char>, stop_words: &'static HashSet<&'static str>) -> Vec<String> {
+        text.split(|c| separators.contains(&c))
+            .filter(|word| !word.is_empty() && !stop_words.contains(word))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// How many times the banner was loaded
+    pub static BANNER_LOADED: AtomicUsize = AtomicUsize::new(0);
+
+    pub fn load_banner() -> String {
+        BANNER_LOADED.fetch_add(1, Ordering::SeqCst);
+        "~~ welcome ~~".to_owned()
+    }
+
+    #[gen_struct_sugar(defaults(banner = once "load_banner()", widths = once "vec![4, 8]"), positionals(name))]
+    pub fn header(name: &'static str, banner: &'static str, widths: &'static [usize]) -> String {
+        format!("{} {} {:?}", banner, name, widths)
+    }
+
+    #[gen_struct_sugar(defaults(word = try "text.split_whitespace().next()?"), positionals(text))]
+    pub fn shout(text: &'static str, word: &'static str) -> Option<String> {
+        Some(format!("{}!", word.to_uppercase()))
+    }
+
+    fn default_port(scheme: &str) -> Result<u16, String> {
+        match scheme {
+            "http" => Ok(80),
+            "https" => Ok(443),
+            other => Err(format!("no default port for `{}`", other)),
+        }
+    }
+
+    #[gen_struct_sugar(defaults(scheme = r#""http""#, port = try "default_port(scheme)?"), positionals(host))]
+    pub fn address(host: &'static str, scheme: &'static str, port: u16) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(format!("{}://{}:{}", scheme, host, port))
+    }
+
+    async fn content_length(url: &'static str) -> usize {
+        url.len() * 10
+    }
+
+    #[gen_struct_sugar(defaults(retries = "3"), positionals(url))]
+    pub async fn fetch_page(url: &'static str, retries: u32) -> String {
+        format!("Fetched {} bytes from {} ({} retries)", content_length(url).await, url, retries)
+    }
+
+    #[gen_struct_sugar(defaults(retries = "3"), positionals(url), validate(retries = "retries <= 5"))]
+    pub async fn fetch_checked(url: &'static str, retries: u32) -> Result<Option<usize>, ArgError> {
+        if url.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(content_length(url).await))
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub struct Table {
+        pub entries: [u32; 4],
+        pub label: Option<&'static str>,
+    }
+
+    #[gen_struct_sugar(defaults(scale = "1", offset = "0", label = "None"), positionals(first))]
+    pub const fn table(first: u32, scale: u32, offset: u32, label: Option<&'static str>) -> Table {
+        let mut entries = [0; 4];
+        let mut i = 0;
+        while i < entries.len() {
+            entries[i] = (first + i as u32) * scale + offset;
+            i += 1;
+        }
+        Table { entries, label }
+    }
+
+    /// The builders of const fns don't use `unsafe`, so they work where it's forbidden
+    pub mod checked_tables {
+        #![forbid(unsafe_code)]
+        use super::*;
+
+        #[gen_struct_sugar(defaults(step = "1", label = "None"), positionals(start))]
+        pub const fn steps(start: u32, step: u32, label: Option<&'static str>) -> Table {
+            Table { entries: [start, start + step, start + 2 * step, start + 3 * step], label }
+        }
+    }
+
+    /// Describes a call - a parameter can be named `call` like any other
+    #[gen_struct_sugar(defaults(minutes = "1"))]
+    pub fn log_call(call: &'static str, minutes: u32) -> String {
+        format!("{} ({} min)", call, minutes)
+    }
+
+    /// Sums `len` values starting at `values`
+    ///
+    /// # Safety
+    ///
+    /// `values` has to point to at least `len` initialized values
+    #[gen_struct_sugar(defaults(len = "1"), positionals(values))]
+    pub unsafe fn sum_raw(values: *const u32, len: usize) -> u32 {
+        ::std::slice::from_raw_parts(values, len).iter().sum()
+    }
+
+    pub extern "C" fn double(value: i32) -> i32 {
+        value * 2
+    }
+
+    #[gen_struct_sugar(defaults(op = "double"), positionals(value))]
+    pub extern "C" fn apply_op(value: i32, op: extern "C" fn(i32) -> i32) -> i32 {
+        op(value)
+    }
+
+    #[gen_struct_sugar(defaults(step = "1", count = "10"), positionals(start), ffi)]
+    pub fn range_sum(start: i64, step: i64, count: u32) -> i64 {
+        (0..i64::from(count)).map(|i| start + i * step).sum()
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[repr(C)]
+    pub struct Point {
+        pub x: i32,
+        pub y: i32,
+    }
+
+    #[gen_struct_sugar(
+        defaults(dx = "0", dy = "0"),
+        positionals(from),
+        ffi(symbol = "example_api_translate", repr_c(Point))
+    )]
+    pub fn translate(from: Point, dx: i32, dy: i32) -> Point {
+        Point { x: from.x + dx, y: from.y + dy }
+    }
+
+    #[gen_struct_sugar(
+        defaults(by = "&Point { x: 1, y: 1 }"),
+        optional(limit),
+        positionals(from),
+        ffi(repr_c(Point))
+    )]
+    pub fn offset(from: Point, by: &'static Point, limit: Option<&'static Point>) -> Point {
+        let (x, y) = (from.x + by.x, from.y + by.y);
+        match limit {
+            Some(limit) => Point { x: x.min(limit.x), y: y.min(limit.y) },
+            None => Point { x, y },
+        }
+    }
+
+    #[gen_struct_sugar(defaults(end = "::std::ptr::null_mut()", base = "10"), positionals(text))]
+    extern "C" {
+        /// Parses a number from a C string
+        pub fn strtol(
+            text: *const ::std::os::raw::c_char,
+            end: *mut *mut ::std::os::raw::c_char,
+            base: ::std::os::raw::c_int,
+        ) -> ::std::os::raw::c_long;
+    }
+
+    #[gen_struct_sugar(defaults(value = "-3"), safe)]
+    extern "C" {
+        #[link_name = "abs"]
+        pub fn c_abs(value: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
+    }
+
+    rubber_duck::adapt! {
+        /// The larger of two numbers
+        pub fn std::cmp::max(a: i32, b: i32 = 0) -> i32;
+
+        #[gen_struct_sugar(positionals(base))]
+        pub fn u32::pow(
+            base: u32,
+            /// The power to raise `base` to
+            exp: u32 = 2,
+        ) -> u32;
+
+        /// Reads bytes as a string, without checking them
+        ///
+        /// # Safety
+        ///
+        /// The bytes have to be valid UTF-8
+        pub unsafe fn std::str::from_utf8_unchecked(v: &'static [u8] = b"none") -> &'static str;
+    }
+
+    rubber_duck::named_fn! {
+        /// Greets someone, excitedly if asked to
+        pub fn welcome(
+            name: &'static str,
+            *,
+            #[into]
+            greeting: String = "Welcome".to_owned(),
+            /// Whether to end with a `!`
+            excited: bool = false,
+        ) -> String {
+            let end = if excited { "!" } else { "." };
+            format!("{}, {}{}", &greeting, name, end)
+        }
+    }
+
+    rubber_duck::named_fn! {
+        /// Cuts `text` down to `width` characters, marking where it was cut
+        pub fn truncate(
+            text: &'static str,
+            #[derive(Default)]
+            options: {
+                /// Defaults to the whole text, so nothing is cut
+                width: usize = text.chars().count(),
+                #[into]
+                marker: String = "...".to_owned(),
+            },
+        ) -> String {
+            if text.chars().count() <= width {
+                text.to_owned()
+            } else {
+                text.chars().take(width).collect::<String>() + &marker
+            }
+        }
+    }
+
+    /// Pads `text` on the right, up to `width` characters
+    #[gen_struct_sugar(defaults(width = "text.len() + 1", fill = "' '"), positionals(text), named_struct = "options")]
+    pub fn pad(text: &'static str, width: usize, fill: char) -> String {
+        let padding = fill.to_string().repeat(width.saturating_sub(text.len()));
+        format!("{}{}", text, padding)
+    }
+
+    /// Shows `value` with a label - both can be given as any type that fits
+    #[gen_struct_sugar(defaults(precision = "2"), positionals(value))]
+    pub fn show(value: impl Into<f64>, label: impl std::fmt::Display, precision: usize) -> String {
+        format!("{}: {:.*}", label, precision, value.into())
+    }
+
+    /// Puts `edge` on both sides of `text`, `times` times
+    #[gen_struct_sugar(defaults(times = "1"), positionals(text), named_struct = "options")]
+    pub fn surround(text: &'static str, edge: impl std::fmt::Display, times: usize) -> String {
+        let edge = edge.to_string().repeat(times);
+        format!("{}{}{}", edge, text, edge)
+    }
+
+    #[gen_struct_sugar(defaults(greeting = r#""Hi".to_owned()"#))]
+    pub(crate) fn crate_greet(name: String, greeting: String) -> String {
+        format!("{}, {}!", &greeting, &name)
+    }
+
+    /// The settings a `Client` falls back on
+    pub struct ClientConfig {
+        pub timeout: u32,
+        pub retries: u32,
+    }
+
+    pub struct Client {
+        pub name: &'static str,
+        pub config: ClientConfig,
+        pub requests: u32,
+    }
+
+    #[gen_struct_sugar]
+    impl Client {
+        pub fn new(name: &'static str) -> Self {
+            Client { name, config: ClientConfig { timeout: 30, retries: 1 }, requests: 0 }
+        }
+
+        /// Describes a request for `path`, using the client's settings unless given others
+        #[gen_struct_sugar(
+            defaults(timeout = "self.config.timeout", retries = "self.config.retries"),
+            positionals(path)
+        )]
+        pub fn get(
+            &self,
+            path: &'static str,
+            /// In seconds
+            timeout: u32,
+            retries: u32,
+        ) -> String {
+            format!("{} GET {} (timeout {}s, {} retries)", self.name, path, timeout, retries)
+        }
+
+        /// Counts requests made outside the client
+        #[gen_struct_sugar(defaults(count = "1"))]
+        pub fn record(&mut self, count: u32) -> u32 {
+            self.requests += count;
+            self.requests
+        }
+
+        /// Labels `value` with the client's name
+        #[gen_struct_sugar(defaults(separator = r#"": ""#))]
+        pub fn label(&self, value: impl std::fmt::Debug, separator: &'static str) -> String {
+            format!("{}{}{:?}", self.name, separator, value)
+        }
+
+        #[gen_struct_sugar(defaults(suffix = r#""client".to_owned()"#))]
+        pub fn into_name(self, suffix: String) -> String {
+            format!("{} {}", self.name, suffix)
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Consistency {
+        Eventual,
+        Strong,
+    }
+
+    /// Every implementor gets the named arguments, with the defaults of the trait
+    #[gen_struct_sugar]
+    pub trait Store {
+        fn default_consistency(&self) -> Consistency {
+            Consistency::Eventual
+        }
+
+        #[gen_struct_sugar(defaults(consistency = "self.default_consistency()"), positionals(key))]
+        fn get(&self, key: String, consistency: Consistency) -> Option<String>;
+
+        #[gen_struct_sugar(defaults(overwrite = "true"), positionals(key, value))]
+        fn put(&mut self, key: String, value: String, overwrite: bool) -> bool;
+    }
+
+    #[derive(Default)]
+    pub struct MemoryStore {
+        pub entries: std::collections::HashMap<String, String>,
+    }
+
+    impl Store for MemoryStore {
+        fn get(&self, key: String, consistency: Consistency) -> Option<String> {
+            self.entries.get(&key).map(|value| format!("{} {:?}", value, consistency))
+        }
+
+        fn put(&mut self, key: String, value: String, overwrite: bool) -> bool {
+            if !overwrite && self.entries.contains_key(&key) {
+                return false;
+            }
+            self.entries.insert(key, value);
+            true
+        }
+    }
 }
 
 pub mod doc_test {
@@ -97,6 +590,7 @@ mod tests {
     use std::path::PathBuf;
     use crate::n;
 
+    #[cfg(feature = "nightly")]
     #[test]
     fn open_file_works() -> Result<(), Box<Error>> {
         let mut handle = crate::module::open_file!(PathBuf::from("test.txt"), read => true)?;
@@ -124,6 +618,19 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn two_pos_macro_works() {
+        assert_eq!(
+            "1) From earth, hello Bob, It's working! The end.",
+            crate::module::two_pos!("earth".to_owned(), "It's working!".to_owned(),)
+        );
+        assert_eq!(
+            "1) From earth, hello Bill, It's working! The end.",
+            crate::module::two_pos!("earth".to_owned(), "It's working!".to_owned(), name => "Bill".to_owned())
+        );
+    }
+
     #[test]
     fn manual_open_file_works() -> Result<(), Box<Error>> {
         use crate::{Deconstruct, Call};
@@ -150,6 +657,725 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn custom_names_work() {
+        use crate::module::{greet, GreetArgs, GreetBuilder};
+
+        let builder: GreetBuilder<_, _> = GreetArgs::builder().next("Bob".to_owned());
+        let args: GreetArgs = builder.build();
+        assert_eq!("Bob", args.name);
+        assert_eq!(Some("Hello".to_owned()), args.greeting);
+        assert_eq!("Hello, Bob!", n!(greet{"Bob".to_owned()}));
+        assert_eq!("Hi, Bob!", n!(greet{"Bob".to_owned(), {greeting: "Hi".to_owned(),}}));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn custom_macro_name_works() {
+        assert_eq!("Hi, Bob!", crate::module::greet_named!("Bob".to_owned(), greeting => "Hi".to_owned()));
+    }
+
+    #[test]
+    fn struct_attributes_work() {
+        use crate::module::{fetch, FetchArgs};
+
+        let args = FetchArgs::builder().url("example.com".to_owned()).build();
+        assert_eq!(args.clone(), args);
+        assert_eq!(
+            r#"FetchArgs { url: "example.com", retries: Some(3) }"#,
+            format!("{:?}", args)
+        );
+        assert_eq!(
+            "Fetching example.com with 3 retries",
+            n!(fetch{{url: "example.com".to_owned(),}})
+        );
+    }
+
+    #[test]
+    fn constraints_allow_valid_calls() {
+        use crate::module::describe_target;
+
+        assert_eq!(Ok("Path test.txt".to_owned()), n!(describe_target{{path: "test.txt".to_owned(),}}));
+        assert_eq!(Ok("File descriptor 3".to_owned()), n!(describe_target{{fd: 3,}}));
+        assert_eq!(Ok("Path test.txt".to_owned()), describe_target(Some("test.txt".to_owned()), None));
+
+        let mut handle = n!(crate::module::open_file{PathBuf::from("test.txt"), {read: true, append: false}}).unwrap();
+        let mut contents = String::new();
+        handle.read_to_string(&mut contents).unwrap();
+        assert_eq!("hello\n", contents);
+    }
+
+    #[test]
+    fn constraints_checked_on_direct_calls() {
+        assert_eq!(
+            Err(crate::ArgError::new(
+                "path",
+                "exactly one of `path`, `fd` must be set when calling `describe_target`"
+            )),
+            crate::module::describe_target(Some("test.txt".to_owned()), Some(3))
+        );
+    }
+
+    #[test]
+    fn constraints_check_flags_by_value() {
+        let path = std::env::temp_dir().join("rubber_duck_constraints.txt");
+        let open = |append: bool, truncate: bool, create_new: bool| {
+            n!(crate::module::open_file{
+                path.clone(),
+                {write: !create_new, create: true, append: append, truncate: truncate, create_new: create_new}
+            })
+        };
+        assert!(open(false, true, false).is_ok());
+        assert!(open(true, false, false).is_ok());
+        assert!(open(false, false, false).is_ok());
+
+        let err = open(true, true, false).unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!(
+            "Invalid argument `append`: only one of `append`, `truncate` can be set when calling `open_file`",
+            err.to_string()
+        );
+        let err = open(false, false, true).unwrap_err();
+        assert_eq!(
+            "Invalid argument `create_new`: `create_new` requires `write` to be set when calling `open_file`",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn constraints_check_flags_after_defaults() {
+        use crate::module::sync;
+        use crate::ArgError;
+
+        // `verbose` defaults to true, so it counts as set - and `label` only counts when it's set, not its default
+        assert_eq!(Ok("sync (verbose: true, dry run: true)".to_owned()), n!(sync{{dry_run: true,}}));
+        assert_eq!(Ok("sync (verbose: true, dry run: true)".to_owned()), sync(None, Some(true), None));
+        assert_eq!(
+            Ok(Ok("sync (verbose: true, dry run: true)".to_owned())),
+            sync::dyn_builder().dry_run(true).try_call()
+        );
+
+        assert_eq!(
+            Err(ArgError::new("dry_run", "`dry_run` requires `verbose` to be set when calling `sync`")),
+            n!(sync{{dry_run: true, verbose: false}})
+        );
+        assert_eq!(
+            Err(ArgError::new("dry_run", "only one of `dry_run`, `label` can be set when calling `sync`")),
+            n!(sync{{dry_run: true, label: "nightly".to_owned()}})
+        );
+        assert_eq!(
+            Ok("nightly (verbose: false, dry run: false)".to_owned()),
+            n!(sync{{verbose: false, label: "nightly".to_owned()}})
+        );
+    }
+
+    #[test]
+    fn conversions_work() {
+        use crate::module::connect;
+
+        assert_eq!("http://anonymous@example.com:8080", n!(connect{"example.com"}));
+        assert_eq!(
+            "https://bob@example.com:443",
+            n!(connect{"example.com", {scheme: "HTTPS", port: "443", user: "bob"}})
+        );
+        assert_eq!(
+            "http://bob@example.com:8080",
+            n!(connect{"example.com", {user: Some("bob"),}})
+        );
+        assert_eq!(
+            "http://anonymous@example.com:8080",
+            n!(connect{"example.com", {user: None::<String>,}})
+        );
+
+        let mut handle = n!(crate::module::open_file{"test.txt", {read: true,}}).unwrap();
+        let mut contents = String::new();
+        handle.read_to_string(&mut contents).unwrap();
+        assert_eq!("hello\n", contents);
+    }
+
+    #[test]
+    fn validation_works() {
+        use crate::module::{listen, spawn_pool, PoolError};
+        use crate::ArgError;
+
+        assert_eq!(Ok("Listening on localhost:8080".to_owned()), n!(listen{"localhost".to_owned()}));
+        assert_eq!(Ok("Listening on localhost:80".to_owned()), n!(listen{"localhost".to_owned(), {port: 80u64,}}));
+        assert_eq!(
+            Err(ArgError::new("port", "out of range integral type conversion attempted")),
+            n!(listen{"localhost".to_owned(), {port: 70000,}})
+        );
+        assert_eq!(
+            Err(ArgError::new("host", "failed the check `!host.is_empty()`")),
+            listen("".to_owned(), None)
+        );
+
+        assert_eq!(Ok(4), n!(spawn_pool{}));
+        assert_eq!(
+            Err(PoolError::BadArgument(ArgError::new("workers", "failed the check `workers <= 64`"))),
+            n!(spawn_pool{{workers: 100,}})
+        );
+    }
+
+    #[test]
+    fn dyn_builder_works() {
+        use crate::module::{connect, two_posDynBuilder};
+        use crate::MissingArgs;
+
+        let config = vec![("user", "bob"), ("port", "443")];
+        let mut builder = connect::dyn_builder().host("example.com");
+        for (name, value) in config {
+            builder = match name {
+                "user" => builder.user(value),
+                "port" => builder.port(value),
+                _ => builder,
+            };
+        }
+        assert_eq!(Ok("http://bob@example.com:443".to_owned()), builder.try_call());
+
+        assert_eq!(
+            Err(MissingArgs::new(vec!["loc", "message"]).into()),
+            two_posDynBuilder::new().name("Alice".to_owned()).try_call()
+        );
+        let built = two_posDynBuilder::new()
+            .message("Hi.".to_owned())
+            .loc("Home".to_owned())
+            .try_build()
+            .unwrap();
+        assert_eq!(Some("Bob".to_owned()), built.name);
+        assert_eq!(
+            "Missing required arguments: `host`",
+            connect::dyn_builder().try_build().err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn dyn_builder_checks_constraints() {
+        use crate::module::{describe_target, open_file};
+        use crate::{ArgError, BuildError};
+
+        assert_eq!(Ok(Ok("File descriptor 3".to_owned())), describe_target::dyn_builder().fd(3).try_call());
+        assert_eq!(
+            Ok(Ok("Path test.txt".to_owned())),
+            describe_target::dyn_builder().path("test.txt".to_owned()).try_call()
+        );
+        assert_eq!(
+            Err(BuildError::Invalid(ArgError::new(
+                "path",
+                "exactly one of `path`, `fd` must be set when calling `describe_target`"
+            ))),
+            describe_target::dyn_builder().try_call()
+        );
+        assert_eq!(
+            "Invalid argument `path`: exactly one of `path`, `fd` must be set when calling `describe_target`",
+            describe_target::dyn_builder().path("test.txt".to_owned()).fd(3).try_build().err().unwrap().to_string()
+        );
+
+        let mut handle = open_file::dyn_builder().path("test.txt").read(true).try_call().unwrap().unwrap();
+        let mut contents = String::new();
+        handle.read_to_string(&mut contents).unwrap();
+        assert_eq!("hello\n", contents);
+        assert!(open_file::dyn_builder().path("test.txt").read(true).append(false).truncate(true).try_build().is_ok());
+        assert_eq!(
+            Err(BuildError::Invalid(ArgError::new(
+                "append",
+                "only one of `append`, `truncate` can be set when calling `open_file`"
+            ))),
+            open_file::dyn_builder().path("test.txt").append(true).truncate(true).try_build().map(|_| ())
+        );
+    }
+
+    #[test]
+    fn dyn_builder_fills_defaults_and_validates() {
+        use crate::module::{greet, listen};
+        use crate::ArgError;
+
+        assert_eq!(Ok("Hello, Bob!".to_owned()), greet::dyn_builder().name("Bob".to_owned()).try_call());
+        assert_eq!(
+            Ok("Hi, Bob!".to_owned()),
+            greet::dyn_builder().name("Bob".to_owned()).greeting("Hi".to_owned()).try_call()
+        );
+
+        assert_eq!(
+            Ok(Ok("Listening on localhost:8080".to_owned())),
+            listen::dyn_builder().host("localhost".to_owned()).try_call()
+        );
+        assert_eq!(
+            Ok(Err(ArgError::new("host", "failed the check `!host.is_empty()`"))),
+            listen::dyn_builder().host(String::new()).port(80).try_call()
+        );
+    }
+
+    #[test]
+    fn nullable_works() {
+        use crate::module::list_items;
+
+        assert_eq!("10 items (all)", n!(list_items{}));
+        assert_eq!("5 items (all)", n!(list_items{{limit: 5,}}));
+        assert_eq!("unlimited items (unlabelled)", n!(list_items{{limit: None, label: None}}));
+
+        let maybe_limit: Option<Option<u32>> = None;
+        assert_eq!("10 items (all)", n!(list_items{{limit?: maybe_limit,}}));
+        let maybe_limit = Some(Some(3));
+        assert_eq!("3 items (new)", n!(list_items{{limit?: maybe_limit, label: "new".to_owned()}}));
+
+        assert_eq!("10 items (unlabelled)", list_items(None, Some(None)));
+        assert_eq!(
+            Ok("unlimited items (all)".to_owned()),
+            list_items::dyn_builder().limit(None).try_call()
+        );
+    }
+
+    #[test]
+    fn default_shorthands_work() {
+        use crate::module::schedule;
+
+        assert_eq!("build (no timeout, 3 retries)", n!(schedule{"build"}));
+        assert_eq!(
+            "build [nightly] (30s, 3 retries)",
+            n!(schedule{"build", {timeout: 30, tag: "nightly".to_owned()}})
+        );
+        let tag: Option<String> = None;
+        assert_eq!("build (no timeout, 3 retries)", n!(schedule{"build", {tag?: tag,}}));
+        assert_eq!(
+            "build [manual] (no timeout, 1 retries)",
+            schedule("build".to_owned(), None, Some(1), Some("manual".to_owned()))
+        );
+    }
+
+    /// Runs `body` with the environment variables set, removing them again afterwards. The environment is shared
+    /// by every test thread, so tests that change it take turns
+    fn with_env<R>(vars: &[(&'static str, &str)], body: impl FnOnce() -> R) -> R {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        // Removes the variables even if `body` panics
+        struct Remove(Vec<&'static str>);
+        impl Drop for Remove {
+            fn drop(&mut self) {
+                for name in &self.0 {
+                    std::env::remove_var(name);
+                }
+            }
+        }
+
+        let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let _remove = Remove(vars.iter().map(|(name, _)| *name).collect());
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+        body()
+    }
+
+    #[test]
+    fn runtime_defaults_work() {
+        use crate::module::serve;
+
+        with_env(&[], || {
+            assert_eq!(
+                Err(ArgError::new(
+                    "name",
+                    "wasn't given, and has no default in the environment variable `EXAMPLE_API_NAME` or the config file `config/app.toml`"
+                )),
+                n!(serve{})
+            );
+            assert_eq!(
+                Ok("api on 0.0.0.0:8080 with 2 workers".to_owned()),
+                n!(serve{{name: "api".to_owned(),}})
+            );
+        });
+
+        with_env(&[("EXAMPLE_API_NAME", "env-api"), ("EXAMPLE_API_PORT", "9000")], || {
+            assert_eq!(Ok("env-api on 0.0.0.0:9000 with 2 workers".to_owned()), n!(serve{}));
+            assert_eq!(
+                Ok("env-api on 127.0.0.1:9000 with 2 workers".to_owned()),
+                n!(serve{{host: "127.0.0.1".to_owned(),}})
+            );
+        });
+
+        with_env(&[("EXAMPLE_API_NAME", "env-api"), ("EXAMPLE_API_PORT", "ninety")], || {
+            assert_eq!(
+                Err(ArgError::new(
+                    "port",
+                    "couldn't parse `ninety` from the environment variable `EXAMPLE_API_PORT`: invalid digit found in string"
+                )),
+                n!(serve{})
+            );
+        });
+    }
+
+    #[test]
+    fn overridden_defaults_work() {
+        use crate::module::download;
+        use rubber_duck::with_defaults;
+
+        assert_eq!("Downloading a.txt (30s timeout, 3 retries)", n!(download{"a.txt"}));
+        with_defaults(download::overrides().timeout(1), || {
+            assert_eq!("Downloading a.txt (1s timeout, 3 retries)", n!(download{"a.txt"}));
+            assert_eq!("Downloading a.txt (5s timeout, 3 retries)", n!(download{"a.txt", {timeout: 5,}}));
+
+            with_defaults(download::overrides().retries(0), || {
+                assert_eq!("Downloading a.txt (1s timeout, 0 retries)", download("a.txt", None, None));
+            });
+            assert_eq!("Downloading a.txt (1s timeout, 3 retries)", download("a.txt", None, None));
+        });
+
+        let panicked = std::panic::catch_unwind(|| {
+            with_defaults(download::overrides().timeout(1), || panic!("Download failed"))
+        });
+        assert!(panicked.is_err());
+        assert_eq!("Downloading a.txt (30s timeout, 3 retries)", n!(download{"a.txt"}));
+    }
+
+    #[test]
+    fn cfg_defaults_work() {
+        use crate::module::poll;
+
+        let retries = if cfg!(feature = "nightly") { 0 } else { 3 };
+        assert_eq!(format!("Polling every 1s, {} retries", retries), n!(poll{}));
+        assert_eq!(format!("Polling every 5s, {} retries", retries), n!(poll{{timeout: 5,}}));
+        assert_eq!("Polling every 1s, 2 retries", poll(None, Some(2)));
+    }
+
+    #[test]
+    fn once_defaults_work() {
+        use crate::module::{tokenize, STOP_WORDS_LOADED};
+        use std::collections::HashSet;
+        use std::sync::atomic::Ordering;
+
+        assert_eq!(vec!["cat", "sat"], n!(tokenize{"the cat, sat"}));
+        assert_eq!(vec!["dog", "cat"], n!(tokenize{"a dog;the cat", {separators: vec![' ', ';']}}));
+        assert_eq!(vec!["an", "owl"], n!(tokenize{"an owl", {stop_words: &*Box::leak(Box::new(HashSet::new()))}}));
+        assert_eq!(vec!["ox"], tokenize("an ox", None, None));
+        assert_eq!(1, STOP_WORDS_LOADED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn once_defaults_cache_unsized_references() {
+        use crate::module::{header, BANNER_LOADED};
+        use std::sync::atomic::Ordering;
+
+        assert_eq!("~~ welcome ~~ Ann [4, 8]", n!(header{"Ann"}));
+        assert_eq!("~~ welcome ~~ Bo [2]", n!(header{"Bo", {widths: &[2][..]}}));
+        assert_eq!("hi Cy [4, 8]", n!(header{"Cy", {banner: "hi"}}));
+        assert_eq!(1, BANNER_LOADED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn try_defaults_work() {
+        use crate::module::{address, shout};
+
+        assert_eq!(Some("HELLO!".to_owned()), n!(shout{"hello world"}));
+        assert_eq!(Some("WORLD!".to_owned()), n!(shout{"", {word: "world"}}));
+        assert_eq!(None, n!(shout{""}));
+
+        assert_eq!("http://localhost:80", n!(address{"localhost"}).unwrap());
+        assert_eq!("https://localhost:443", n!(address{"localhost", {scheme: "https"}}).unwrap());
+        assert_eq!("ftp://localhost:21", n!(address{"localhost", {scheme: "ftp", port: 21}}).unwrap());
+        assert_eq!(
+            "no default port for `ftp`",
+            n!(address{"localhost", {scheme: "ftp"}}).unwrap_err().to_string()
+        );
+    }
+
+    /// Runs a future to completion, for the async fns that never wait on anything
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        use std::task::{Context, Poll, Waker};
+
+        let mut future = std::pin::pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(value) = future.as_mut().poll(&mut context) {
+                return value;
+            }
+        }
+    }
+
+    #[test]
+    fn async_fns_work() {
+        use crate::module::{fetch_checked, fetch_page};
+
+        assert_eq!("Fetched 50 bytes from a.com (3 retries)", block_on(n!(fetch_page{"a.com"})));
+        assert_eq!("Fetched 50 bytes from a.com (1 retries)", block_on(n!(fetch_page{"a.com", {retries: 1}})));
+        assert_eq!(
+            "Fetched 50 bytes from a.com (3 retries)",
+            block_on(fetch_page::dyn_builder().url("a.com").try_call().unwrap())
+        );
+        assert!(fetch_page::dyn_builder().try_call().is_err());
+
+        assert_eq!(Some(50), block_on(n!(fetch_checked{"a.com"})).unwrap());
+        assert_eq!(None, block_on(n!(fetch_checked{""})).unwrap());
+        assert_eq!("retries", block_on(n!(fetch_checked{"a.com", {retries: 9}})).unwrap_err().param);
+    }
+
+    #[test]
+    fn const_fns_work() {
+        use crate::module::{table, Table};
+
+        const PLAIN: Table = n!(table{1});
+        static SCALED: Table = n!(table{1, {scale: 10, label: Some("tens")}});
+        assert_eq!([1, 2, 3, 4], PLAIN.entries);
+        assert_eq!(None, PLAIN.label);
+        assert_eq!([10, 20, 30, 40], SCALED.entries);
+        assert_eq!(Some("tens"), SCALED.label);
+
+        const SHIFTED: Table = table::builder().next(0).offset(5).maybe_scale(None).call();
+        assert_eq!([5, 6, 7, 8], SHIFTED.entries);
+        assert_eq!(SHIFTED, n!(table{0, {offset: 5}}));
+    }
+
+    #[test]
+    fn const_builders_are_safe() {
+        use crate::module::{checked_tables::steps, Table};
+
+        const EVENS: Table = n!(steps{0, {step: 2, label: Some("evens")}});
+        assert_eq!([0, 2, 4, 6], EVENS.entries);
+        assert_eq!(Some("evens"), EVENS.label);
+        // Setting an argument twice replaces it
+        assert_eq!([1, 4, 7, 10], steps::builder().next(1).step(2).step(3).call().entries);
+    }
+
+    #[test]
+    fn parameters_can_be_named_call() {
+        use crate::module::log_call;
+
+        assert_eq!("standup (15 min)", n!(log_call{{call: "standup", minutes: 15}}));
+        assert_eq!("review (1 min)", log_call::builder().call("review").build().call());
+    }
+
+    #[test]
+    fn unsafe_and_extern_fns_work() {
+        use crate::module::{apply_op, sum_raw};
+
+        extern "C" fn negate(value: i32) -> i32 {
+            -value
+        }
+
+        let values = [1, 2, 3];
+        assert_eq!(1, unsafe { n!(sum_raw{values.as_ptr()}) });
+        assert_eq!(6, unsafe { n!(sum_raw{values.as_ptr(), {len: 3}}) });
+        assert_eq!(3, unsafe { sum_raw::dyn_builder().values(values.as_ptr()).len(2).try_call() }.unwrap());
+
+        assert_eq!(8, n!(apply_op{4}));
+        assert_eq!(-4, n!(apply_op{4, {op: negate as extern "C" fn(i32) -> i32}}));
+        let op: extern "C" fn(i32, Option<extern "C" fn(i32) -> i32>) -> i32 = apply_op;
+        assert_eq!(10, op(5, None));
+    }
+
+    #[test]
+    fn ffi_works() {
+        use crate::module::{range_sum, range_sumFfiArgs, range_sum_ffi};
+
+        let zeroed = range_sumFfiArgs { start: 1, has_step: false, step: 0, has_count: false, count: 0 };
+        assert_eq!(n!(range_sum{1}), range_sum_ffi(zeroed));
+        let set = range_sumFfiArgs { start: 1, has_step: true, step: 2, has_count: true, count: 3 };
+        assert_eq!(9, range_sum_ffi(set));
+
+        assert_eq!(
+            "typedef struct range_sumFfiArgs {\n    \
+                 int64_t start;\n    \
+                 bool has_step; /* defaults to `1` */\n    \
+                 int64_t step;\n    \
+                 bool has_count; /* defaults to `10` */\n    \
+                 uint32_t count;\n\
+             } range_sumFfiArgs;\n\n\
+             int64_t range_sum_ffi(range_sumFfiArgs args);\n",
+            range_sumFfiArgs::C_HEADER
+        );
+        let header = rubber_duck::ffi::c_header("EXAMPLE_API_H", &[range_sumFfiArgs::C_HEADER]);
+        assert!(header.contains(range_sumFfiArgs::C_HEADER));
+    }
+
+    #[test]
+    fn ffi_takes_listed_types_and_symbols() {
+        use crate::module::{translate_ffi, translateFfiArgs, Point};
+
+        let args = translateFfiArgs { from: Point { x: 1, y: 2 }, has_dx: true, dx: 3, has_dy: false, dy: 0 };
+        assert_eq!(Point { x: 4, y: 2 }, translate_ffi(args));
+        assert!(translateFfiArgs::C_HEADER.contains("    Point from;\n"));
+        assert!(translateFfiArgs::C_HEADER.ends_with("Point example_api_translate(translateFfiArgs args);\n"));
+    }
+
+    #[test]
+    fn ffi_keeps_references_nullable() {
+        use crate::module::{offset_ffi, offsetFfiArgs, Point};
+
+        // A zeroed reference is a null pointer, which is `None` rather than a null `&Point`
+        let zeroed: offsetFfiArgs = unsafe { std::mem::zeroed() };
+        assert_eq!(Point { x: 1, y: 1 }, offset_ffi(zeroed));
+        let flagged = offsetFfiArgs { from: Point { x: 1, y: 1 }, has_by: true, by: None, has_limit: true, limit: None };
+        assert_eq!(Point { x: 2, y: 2 }, offset_ffi(flagged));
+
+        static BY: Point = Point { x: 5, y: 0 };
+        static LIMIT: Point = Point { x: 4, y: 4 };
+        let set = offsetFfiArgs {
+            from: Point { x: 1, y: 1 },
+            has_by: true,
+            by: Some(&BY),
+            has_limit: true,
+            limit: Some(&LIMIT),
+        };
+        assert_eq!(Point { x: 4, y: 1 }, offset_ffi(set));
+
+        assert!(offsetFfiArgs::C_HEADER.contains("    const Point* by;\n"));
+        assert!(offsetFfiArgs::C_HEADER.contains("    const Point* limit;\n"));
+    }
+
+    #[test]
+    fn extern_fns_work() {
+        use crate::module::{c_abs, strtol};
+        use std::ffi::CString;
+
+        let text = CString::new("ff").unwrap();
+        assert_eq!(0, unsafe { n!(strtol{text.as_ptr()}) });
+        assert_eq!(255, unsafe { n!(strtol{text.as_ptr(), {base: 16}}) });
+
+        assert_eq!(3, n!(c_abs{}));
+        assert_eq!(7, n!(c_abs{{value: -7}}));
+        assert_eq!(5, c_abs(Some(5)));
+    }
+
+    #[test]
+    fn adapted_fns_work() {
+        use crate::module::{from_utf8_unchecked, max, pow};
+
+        assert_eq!(0, n!(max{{a: -5}}));
+        assert_eq!(7, n!(max{{a: 3, b: 7}}));
+        assert_eq!(9, n!(pow{3}));
+        assert_eq!(1024, n!(pow{2, {exp: 10}}));
+        assert_eq!(8, pow::dyn_builder().base(2).exp(3).try_call().unwrap());
+        assert_eq!("none", unsafe { n!(from_utf8_unchecked{}) });
+        assert_eq!("hi", unsafe { n!(from_utf8_unchecked{{v: &b"hi"[..]}}) });
+    }
+
+    #[test]
+    fn named_fns_work() {
+        use crate::module::welcome;
+
+        assert_eq!("Welcome, Ann.", n!(welcome{"Ann"}));
+        assert_eq!("Hi, Ann!", n!(welcome{"Ann", {greeting: "Hi", excited: true}}));
+        assert_eq!("Hey, Bo.", welcome::builder().next("Bo").greeting("Hey").call());
+    }
+
+    #[test]
+    fn named_structs_work() {
+        use crate::module::{truncate, TruncateArgs};
+
+        assert_eq!("Hello world", n!(truncate{"Hello world"}));
+        assert_eq!("Hello...", n!(truncate{"Hello world", {width: 5}}));
+        assert_eq!("Hello~", truncate::builder().next("Hello world").width(5).marker("~").call());
+        assert_eq!("Hello world", truncate("Hello world", TruncateArgs::default()));
+        assert_eq!(
+            "He..",
+            truncate("Hello world", TruncateArgs { width: Some(2), marker: Some("..".to_owned()) })
+        );
+    }
+
+    #[test]
+    fn named_struct_option_works() {
+        use crate::module::{pad, PadArgs};
+
+        assert_eq!("ab ", n!(pad{"ab"}));
+        assert_eq!("ab..", n!(pad{"ab", {width: 4, fill: '.'}}));
+        assert_eq!("ab   ", pad::builder().next("ab").width(5).call());
+        assert_eq!("ab ", pad("ab", PadArgs { width: None, fill: None }));
+        assert_eq!("ab--", pad("ab", PadArgs { width: Some(4), fill: Some('-') }));
+    }
+
+    #[test]
+    fn impl_trait_parameters_are_generic() {
+        use crate::module::{show, surround, Client, SurroundArgs};
+
+        assert_eq!("ratio: 1.23", n!(show{1.23456, {label: "ratio"}}));
+        assert_eq!("x: 1.5", n!(show{1.5f32, {label: 'x', precision: 1}}));
+        assert_eq!("two: 2.00", show::builder().next(2).label("two").call());
+        assert_eq!(
+            Ok("two: 2".to_owned()),
+            show::dyn_builder().value(2u8).label(String::from("two")).precision(0).try_call()
+        );
+        assert_eq!("three: 3.00", show(3, "three", None));
+
+        assert_eq!("*a*", n!(surround{"a", {edge: '*'}}));
+        assert_eq!("<><>a<><>", n!(surround{"a", {edge: "<>", times: 2}}));
+        assert_eq!("1a1", surround("a", SurroundArgs { edge: 1, times: None }));
+
+        let client = Client::new("api");
+        assert_eq!("api: [1, 2]", n!(client.label{{value: vec![1, 2]}}));
+        assert_eq!("api = \"a\"", n!(client.label{{value: "a", separator: " = "}}));
+    }
+
+    #[test]
+    fn crate_visible_works() {
+        use crate::module::crate_greet;
+
+        assert_eq!("Hi, Bob!", n!(crate_greet{{name: "Bob".to_owned(),}}));
+    }
+
+    #[test]
+    fn methods_work() {
+        use crate::module::{Client, Client_get, Client_record};
+
+        let mut client = Client::new("api");
+        assert_eq!("api GET /a (timeout 30s, 1 retries)", n!(client.get{"/a"}));
+        assert_eq!("api GET /a (timeout 5s, 1 retries)", n!(client.get{"/a", {timeout: 5}}));
+        assert_eq!("api GET /b (timeout 5s, 1 retries)", Client_get::builder().next("/b").timeout(5).call(&client));
+        assert_eq!(
+            "api GET /c (timeout 30s, 2 retries)",
+            Client_get::dyn_builder().path("/c").retries(2).try_call(&client).unwrap()
+        );
+        assert_eq!("api GET /d (timeout 30s, 3 retries)", client.get("/d", None, Some(3)));
+
+        client.config.timeout = 10;
+        assert_eq!("api GET /a (timeout 10s, 1 retries)", n!(client.get{"/a"}));
+
+        assert_eq!(1, n!(client.record{}));
+        assert_eq!(3, n!(client.record{{count: 2}}));
+        assert_eq!(4, Client_record::builder().call(&mut client));
+        assert_eq!("api client", n!(client.into_name{}));
+        assert_eq!("api server", n!(Client::new("api").into_name{{suffix: "server".to_owned()}}));
+    }
+
+    #[test]
+    fn trait_methods_have_named_arguments() {
+        use crate::module::{Consistency, MemoryStore, Store, Store_get};
+
+        let mut store = MemoryStore::default();
+        assert!(n!(store.put{"a".to_owned(), "1".to_owned()}));
+        assert!(!n!(store.put{"a".to_owned(), "2".to_owned(), {overwrite: false}}));
+        assert_eq!(Some("1 Eventual".to_owned()), n!(store.get{"a".to_owned(), {}}));
+        assert_eq!(Some("1 Strong".to_owned()), n!(store.get{"a".to_owned(), {consistency: Consistency::Strong}}));
+
+        // Through generics, and trait objects
+        fn get_strong<S: Store + ?Sized>(store: &S, key: &str) -> Option<String> {
+            n!(store.get{key.to_owned(), {consistency: Consistency::Strong}})
+        }
+        assert_eq!(Some("1 Strong".to_owned()), get_strong(&store, "a"));
+
+        struct Replica(MemoryStore);
+        impl Store for Replica {
+            fn default_consistency(&self) -> Consistency {
+                Consistency::Strong
+            }
+            fn get(&self, key: String, consistency: Consistency) -> Option<String> {
+                self.0.get(key, consistency)
+            }
+            fn put(&mut self, _key: String, _value: String, _overwrite: bool) -> bool {
+                false
+            }
+        }
+        let mut boxed: Box<dyn Store> = Box::new(store);
+        assert!(n!(boxed.put{"b".to_owned(), "2".to_owned()}));
+        assert_eq!(Some("2 Eventual".to_owned()), n!(boxed.get{"b".to_owned(), {}}));
+        assert_eq!(Some("2 Strong".to_owned()), get_strong(&*boxed, "b"));
+        let shared: &(dyn Store + Send) = &Replica(MemoryStore::default());
+        assert_eq!(None, n!(shared.get{"b".to_owned(), {}}));
+
+        let replica = Replica(MemoryStore { entries: vec![("c".to_owned(), "3".to_owned())].into_iter().collect() });
+        assert_eq!(Some("3 Strong".to_owned()), Store_get::builder().next("c".to_owned()).call(&replica));
+        assert_eq!(
+            Some("2 Eventual".to_owned()),
+            Store_get::dyn_builder().key("b".to_owned()).try_call(&*boxed).unwrap()
+        );
+    }
+
+    #[cfg(feature = "nightly")]
     #[test]
     fn only_named_works() {
         assert_eq!(
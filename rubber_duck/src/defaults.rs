@@ -0,0 +1,214 @@
+//! Lookups for defaults that are read from outside the program when the function is called
+
+use crate::ArgError;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Reads a default from an environment variable, if it's set
+pub fn from_env<T>(param: &'static str, var: &str) -> Result<Option<T>, ArgError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let source = format!("the environment variable `{}`", var);
+    match ::std::env::var(var) {
+        Ok(raw) => parse_value(param, &source, &raw).map(Some),
+        Err(::std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(ArgError::new(param, format!("couldn't read {}: {}", source, e))),
+    }
+}
+
+/// Reads a default from a config file, if the file exists and has the key. A key in a `[section]` named after
+/// the function takes precedence over one at the top of the file. A relative path is resolved against the working
+/// directory, like any other file the program opens. Each file is only read and parsed the first time one of its
+/// defaults is needed - later changes to it aren't picked up
+pub fn from_file<T>(param: &'static str, path: &str, section: &str, key: &str) -> Result<Option<T>, ArgError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let source = format!("the config file `{}`", path);
+    let values = read_config(path).map_err(|reason| ArgError::new(param, reason))?;
+
+    let in_section = values.iter().find(|(s, k, _)| s == section && k == key);
+    let top_level = values.iter().find(|(s, k, _)| s.is_empty() && k == key);
+    match in_section.or(top_level) {
+        Some((_, _, raw)) => parse_value(param, &format!("`{}` in {}", key, source), raw).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The (section, key, value) triples of a config file
+type ConfigValues = Vec<(String, String, String)>;
+
+/// The values of each config file read so far, or why it couldn't be read, by path
+type ConfigFiles = HashMap<String, Result<Arc<ConfigValues>, String>>;
+
+/// The values in a config file, or why it couldn't be read. Files are cached by path, as every defaulted
+/// parameter of every call looks them up
+fn read_config(path: &str) -> Result<Arc<ConfigValues>, String> {
+    static FILES: OnceLock<Mutex<ConfigFiles>> = OnceLock::new();
+    let mut files = FILES.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    files
+        .entry(path.to_owned())
+        .or_insert_with(|| {
+            let source = format!("the config file `{}`", path);
+            let contents = match ::std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(format!("couldn't read {}: {}", source, e)),
+            };
+            parse_config(&contents)
+                .map(Arc::new)
+                .map_err(|(line, e)| format!("couldn't read {}, line {}: {}", source, line, e))
+        }).clone()
+}
+
+/// The error for a default that's needed but wasn't found in any of its sources
+pub fn missing(param: &'static str, sources: &str) -> ArgError {
+    ArgError::new(param, format!("wasn't given, and has no default in {}", sources))
+}
+
+/// Gets a clone of a `once` default, making it the first time it's needed
+pub fn cached<T: Clone + Send + Sync + 'static>(cache: &'static OnceLock<T>, make: impl FnOnce() -> T) -> T {
+    cache.get_or_init(make).clone()
+}
+
+/// Gets a reference to a `once` default, for `&'static` parameters - so the value doesn't need to be `Clone`
+pub fn cached_ref<T: Send + Sync + 'static>(cache: &'static OnceLock<T>, make: impl FnOnce() -> T) -> &'static T {
+    cache.get_or_init(make)
+}
+
+/// Like `cached_ref`, for `&'static str` and `&'static [T]` parameters - the unsized value is cached in a box, made
+/// from whatever the expression gives (e.g. a `String` or a `Vec<T>`)
+pub fn cached_boxed<T, V>(cache: &'static OnceLock<Box<T>>, make: impl FnOnce() -> V) -> &'static T
+where
+    T: ?Sized + Send + Sync + 'static,
+    V: Into<Box<T>>,
+{
+    cache.get_or_init(|| make().into())
+}
+
+fn parse_value<T>(param: &'static str, source: &str, raw: &str) -> Result<T, ArgError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    raw.parse()
+        .map_err(|e: T::Err| ArgError::new(param, format!("couldn't parse `{}` from {}: {}", raw, source, e)))
+}
+
+/// A simple parser for TOML-like files - `key = value` lines, under optional `[section]` headers, with `#` comments.
+/// Values can be quoted (with `\"`, `\\` and `\n` escapes), or are taken as is. Returns (section, key, value) triples
+fn parse_config(contents: &str) -> Result<ConfigValues, (usize, String)> {
+    let mut section = String::new();
+    let mut values = vec![];
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err((idx + 1, "expected a `]` at the end of the section".to_owned()));
+            }
+            section = line[1..line.len() - 1].trim().to_owned();
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap_or_default().trim();
+        let value = match parts.next() {
+            Some(value) if !key.is_empty() => value.trim(),
+            _ => return Err((idx + 1, "expected `key = value`".to_owned())),
+        };
+        let value = if value.starts_with('"') {
+            unquote(value).map_err(|e| (idx + 1, e))?
+        } else {
+            // Comments can follow unquoted values
+            value.split('#').next().unwrap_or_default().trim().to_owned()
+        };
+        values.push((section.clone(), key.to_owned(), value));
+    }
+    Ok(values)
+}
+
+fn unquote(value: &str) -> Result<String, String> {
+    let mut unquoted = String::new();
+    let mut chars = value[1..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let rest: String = chars.collect();
+                let rest = rest.trim();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(format!("unexpected `{}` after the quoted value", rest));
+                }
+                return Ok(unquoted);
+            }
+            '\\' => match chars.next() {
+                Some('"') => unquoted.push('"'),
+                Some('\\') => unquoted.push('\\'),
+                Some('n') => unquoted.push('\n'),
+                other => return Err(format!("unknown escape `\\{}`", other.map(String::from).unwrap_or_default())),
+            },
+            c => unquoted.push(c),
+        }
+    }
+    Err("expected a `\"` at the end of the value".to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_config() {
+        let values = parse_config(
+            r#"
+            # The defaults
+            port = 8080 # The port
+            name = "The \"app\""
+
+            [open_file]
+            port = 9090
+            "#,
+        ).unwrap();
+        assert_eq!(
+            vec![
+                ("".to_owned(), "port".to_owned(), "8080".to_owned()),
+                ("".to_owned(), "name".to_owned(), "The \"app\"".to_owned()),
+                ("open_file".to_owned(), "port".to_owned(), "9090".to_owned()),
+            ],
+            values
+        );
+
+        assert_eq!(Err((2, "expected `key = value`".to_owned())), parse_config("a = 1\nb"));
+        assert!(parse_config("a = \"unterminated").is_err());
+    }
+
+    #[test]
+    fn reads_files_once() {
+        let path = ::std::env::temp_dir().join("rubber_duck_reads_files_once.toml");
+        let path = path.to_str().unwrap();
+        ::std::fs::write(path, "port = 8080\n[serve]\nport = 9090\n").unwrap();
+        assert_eq!(Ok(Some(9090)), from_file::<u16>("port", path, "serve", "port"));
+        assert_eq!(Ok(Some(8080)), from_file::<u16>("port", path, "other", "port"));
+
+        ::std::fs::write(path, "port = 1\n").unwrap();
+        assert_eq!(Ok(Some(9090)), from_file::<u16>("port", path, "serve", "port"));
+        ::std::fs::remove_file(path).unwrap();
+        assert_eq!(Ok(None), from_file::<u16>("port", "missing/config.toml", "serve", "port"));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let err = parse_value::<u16>("port", "the environment variable `APP_PORT`", "abc").unwrap_err();
+        assert_eq!("port", err.param);
+        assert_eq!(
+            "couldn't parse `abc` from the environment variable `APP_PORT`: invalid digit found in string",
+            err.reason
+        );
+    }
+}
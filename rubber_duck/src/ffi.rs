@@ -0,0 +1,30 @@
+//! Support for the `extern "C"` entry points generated with `ffi`
+
+/// Puts together a C header from the `C_HEADER`s of the generated `#[repr(C)]` args structs, e.g. in a build step
+/// or a small binary that writes it out. `guard` is the include guard's name
+pub fn c_header(guard: &str, declarations: &[&str]) -> String {
+    let mut header = format!(
+        "#ifndef {0}\n#define {0}\n\n#include <stdbool.h>\n#include <stdint.h>\n\n\
+         #ifdef __cplusplus\nextern \"C\" {{\n#endif\n",
+        guard
+    );
+    for declaration in declarations {
+        header.push('\n');
+        header.push_str(declaration);
+    }
+    header.push_str(&format!("\n#ifdef __cplusplus\n}}\n#endif\n\n#endif /* {} */\n", guard));
+    header
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn puts_together_headers() {
+        let header = c_header("API_H", &["void a(void);\n", "void b(void);\n"]);
+        assert!(header.starts_with("#ifndef API_H\n#define API_H\n"));
+        assert!(header.contains("extern \"C\" {\n#endif\n\nvoid a(void);\n\nvoid b(void);\n\n#ifdef __cplusplus\n}"));
+        assert!(header.ends_with("#endif /* API_H */\n"));
+    }
+}
@@ -1,6 +1,9 @@
 #![cfg_attr(feature = "nightly", feature(proc_macro_hygiene))]
 #![cfg_attr(feature = "nightly", feature(decl_macro))]
 
+pub mod defaults;
+pub mod ffi;
+
 /// You'll want to glob import this in whatever module you're defining functions to be callable in named/default arg syntax
 pub mod macros {
     pub use rubber_duck_macro::*;
@@ -22,9 +25,152 @@ pub mod core{
         }
     }
 
+    /// Like `AsOption`, but for setters that accept anything convertible into `T` -
+    /// the marker distinguishes a plain value from one that's already wrapped in an option
+    pub trait IntoOptionOf<T, Marker> {
+        fn into_option_of(self) -> ::std::option::Option<T>;
+    }
+    pub struct ViaValue;
+    pub struct ViaOption;
+    impl<T, V: Into<T>> IntoOptionOf<T, ViaValue> for V {
+        fn into_option_of(self) -> ::std::option::Option<T> {
+            Some(self.into())
+        }
+    }
+    impl<T, V: Into<T>> IntoOptionOf<T, ViaOption> for ::std::option::Option<V> {
+        fn into_option_of(self) -> ::std::option::Option<T> {
+            self.map(Into::into)
+        }
+    }
+
+    #[derive(Clone, Copy)]
     pub struct Unset;
 
-    pub use crate::{Call, Deconstruct};
+    /// The state of a builder field that's tracked for constraints - either `Unset` or set to an `Option<T>`
+    pub trait MaybeSet<T> {
+        fn into_option(self) -> ::std::option::Option<T>;
+    }
+    impl<T> MaybeSet<T> for Unset {
+        fn into_option(self) -> ::std::option::Option<T> {
+            None
+        }
+    }
+    impl<T> MaybeSet<T> for ::std::option::Option<T> {
+        fn into_option(self) -> ::std::option::Option<T> {
+            self
+        }
+    }
+
+    pub use crate::{ArgError, BuildError, Call, Deconstruct, DefaultOverrides, MissingArgs};
+    pub use crate::defaults::{
+        cached as cached_default, cached_boxed as cached_default_boxed, cached_ref as cached_default_ref, from_env as default_from_env,
+        from_file as default_from_file, missing as missing_default,
+    };
+}
+
+/// The error returned when an argument fails its validation or conversion,
+/// before the body of the function is run
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgError {
+    /// The name of the parameter
+    pub param: &'static str,
+    /// Why the argument was rejected
+    pub reason: String,
+}
+
+impl ArgError {
+    pub fn new(param: &'static str, reason: impl Into<String>) -> ArgError {
+        ArgError {
+            param,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl ::std::fmt::Display for ArgError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "Invalid argument `{}`: {}", self.param, self.reason)
+    }
+}
+
+impl ::std::error::Error for ArgError {}
+
+/// The error returned by a runtime-checked builder when required arguments weren't set
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingArgs {
+    /// The names of the missing parameters, in declaration order
+    pub missing: Vec<&'static str>,
+}
+
+impl MissingArgs {
+    pub fn new(missing: Vec<&'static str>) -> MissingArgs {
+        MissingArgs { missing }
+    }
+}
+
+impl ::std::fmt::Display for MissingArgs {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let names: Vec<String> = self.missing.iter().map(|v| format!("`{}`", v)).collect();
+        write!(f, "Missing required arguments: {}", names.join(", "))
+    }
+}
+
+impl ::std::error::Error for MissingArgs {}
+
+/// The error returned by a runtime-checked builder when its arguments can't be built
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// Required arguments weren't set
+    Missing(MissingArgs),
+    /// The arguments don't meet one of the function's constraints
+    Invalid(ArgError),
+}
+
+impl From<MissingArgs> for BuildError {
+    fn from(err: MissingArgs) -> BuildError {
+        BuildError::Missing(err)
+    }
+}
+
+impl From<ArgError> for BuildError {
+    fn from(err: ArgError) -> BuildError {
+        BuildError::Invalid(err)
+    }
+}
+
+impl ::std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            BuildError::Missing(err) => err.fmt(f),
+            BuildError::Invalid(err) => err.fmt(f),
+        }
+    }
+}
+
+impl ::std::error::Error for BuildError {}
+
+/// Overrides for some of a function's defaults, generated for its `overridable(..)` parameters
+pub trait DefaultOverrides {
+    /// Puts the overrides in place for the current thread, on top of any that already are
+    fn push(self);
+    /// Removes the overrides that were put in place last
+    fn pop();
+}
+
+/// Runs `body` with some of a function's defaults overridden, e.g. `with_defaults(open_file::overrides().timeout(1), || ...)`.
+/// The overrides apply to every call on the current thread until `body` returns, and can be nested
+pub fn with_defaults<O: DefaultOverrides, R>(overrides: O, body: impl FnOnce() -> R) -> R {
+    // Removes the overrides even if `body` panics
+    struct Pop<O: DefaultOverrides>(::std::marker::PhantomData<O>);
+    impl<O: DefaultOverrides> Drop for Pop<O> {
+        fn drop(&mut self) {
+            O::pop();
+        }
+    }
+
+    overrides.push();
+    let _pop = Pop::<O>(::std::marker::PhantomData);
+    body()
 }
 
 pub trait Call<Args, Res> {
@@ -41,6 +187,12 @@ use proc_macro_hack::proc_macro_hack;
 #[doc = "This allows one to call a function in named/default arg syntax (if support for it was derived)"]
 pub use rubber_duck_macro::n;
 
+/// Gives existing functions a named interface, by declaring them with their defaults inline
+pub use rubber_duck_macro::adapt;
+
+/// Declares a function with its defaults inline, and a `*` between its positional and named parameters
+pub use rubber_duck_macro::named_fn;
+
 macro_rules! impl_call {
     ($($TT:ident),*) => {
         #[allow(non_snake_case)]
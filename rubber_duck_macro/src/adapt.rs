@@ -0,0 +1,111 @@
+use crate::args::Args;
+use crate::inline_params::InlineParam;
+use quote::quote;
+use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
+use proc_macro2::{Delimiter, TokenTree};
+use syn::{parenthesized, parse_quote, Attribute, ItemFn, Path, ReturnType, Token, Visibility};
+
+/// The declarations in `adapt! { .. }`
+pub struct AdaptedFns {
+    pub fns: Vec<AdaptedFn>,
+}
+
+/// An existing function, declared with its defaults inline, e.g. `pub fn std::cmp::max(a: i32, b: i32 = 0) -> i32;`.
+/// Options go in a `#[gen_struct_sugar(..)]` attribute, the same as for an annotated fn
+pub struct AdaptedFn {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    unsafety: Option<Token![unsafe]>,
+    path: Path,
+    params: Punctuated<InlineParam, Token![,]>,
+    output: ReturnType,
+}
+
+impl Parse for AdaptedFns {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut fns = vec![];
+        while !input.is_empty() {
+            fns.push(input.parse()?);
+        }
+        Ok(AdaptedFns { fns })
+    }
+}
+
+impl Parse for AdaptedFn {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        if input.peek(Token![const]) || input.peek(Token![async]) || input.peek(Token![extern]) {
+            return Err(input.error(
+                "Only `fn` and `unsafe fn` can be adapted - the wrapper is a plain fn, so it can't be const, async or \
+                 extern",
+            ));
+        }
+        let unsafety = input.parse()?;
+        input.parse::<Token![fn]>()?;
+        check_not_generic(input)?;
+        let path = input.call(Path::parse_mod_style)?;
+        let content;
+        parenthesized!(content in input);
+        let params = content.parse_terminated(InlineParam::parse)?;
+        let output = input.parse()?;
+        input.parse::<Token![;]>()?;
+        Ok(AdaptedFn { attrs, vis, unsafety, path, params, output })
+    }
+}
+
+/// Generic functions (or functions of generic types) can't be adapted, as the builders aren't generic - so the
+/// path and any generics after it can't have a `<`, up to the parameters
+fn check_not_generic(input: ParseStream) -> Result<()> {
+    input.step(|cursor| {
+        let mut rest = *cursor;
+        while let Some((token, next)) = rest.token_tree() {
+            match token {
+                TokenTree::Group(ref group) if group.delimiter() == Delimiter::Parenthesis => break,
+                TokenTree::Punct(ref punct) if punct.as_char() == '<' => {
+                    return Err(syn::Error::new(
+                        punct.span(),
+                        "Generic functions can't be adapted, as the builders aren't generic - wrap it in a fn with \
+                         the types filled in, and annotate that instead",
+                    ));
+                }
+                _ => rest = next,
+            }
+        }
+        // Nothing is consumed, this only looks ahead
+        Ok(((), *cursor))
+    })
+}
+
+impl AdaptedFn {
+    /// The options, and a wrapper named after the function that forwards to it - which is then treated like
+    /// any annotated fn
+    pub fn into_fn(mut self) -> (Args, ItemFn) {
+        let mut args = crate::inline_params::take_options(&mut self.attrs);
+        let params: Vec<_> = self.params.into_iter().collect();
+        crate::inline_params::add_to_args(&mut args, &params);
+
+        let name = match self.path.segments.iter().last() {
+            Some(segment) => segment.ident.clone(),
+            None => panic!("Expected the path of the function to adapt"),
+        };
+        let attrs = &self.attrs;
+        let vis = &self.vis;
+        let unsafety = &self.unsafety;
+        let path = &self.path;
+        let output = &self.output;
+        let names: Vec<_> = params.iter().map(|p| &p.name).collect();
+        let types = params.iter().map(|p| &p.ty);
+        let names = &names;
+        let call = quote!(#path(#(#names),*));
+        let body = if unsafety.is_some() { quote!(unsafe { #call }) } else { call };
+        let wrapper = parse_quote!(
+            #(#attrs)*
+            #vis #unsafety fn #name(#(#names: #types),*) #output {
+                #body
+            }
+        );
+        (args, wrapper)
+    }
+}
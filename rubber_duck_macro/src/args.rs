@@ -1,13 +1,24 @@
+use crate::attrs::ParsedAttr;
+use crate::constraints::Constraint;
+use crate::defaults::DefaultValue;
+use crate::ffi::Ffi;
+use crate::method::Method;
+use crate::named_struct::NamedParam;
 use quote::quote;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use syn::parse::Parser;
-use syn::{Attribute, Expr, Lit, Meta, MetaNameValue, NestedMeta};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Expr, Ident, Lit, LitStr, Meta, MetaNameValue, NestedMeta, Token, Visibility};
 
-pub fn parse_args(args: ::proc_macro::TokenStream) -> Args {
-    let upper = format!("#[parsing_wrapper({})]", args);
+pub fn parse_args(args: TokenStream) -> Args {
+    // The values in defaults(..) aren't always literals, so they can't be parsed as a `Meta` with the rest
+    let (defaults, cfg_defaults, rest) = split_defaults(args);
+    // Parsed from tokens rather than a string, so the names given keep their spans for errors
+    let upper = quote!(#[parsing_wrapper(#rest)]);
     let attr = Attribute::parse_outer
-        .parse_str(&upper)
+        .parse2(upper)
         .unwrap()
         .pop()
         .unwrap();
@@ -15,28 +26,121 @@ pub fn parse_args(args: ::proc_macro::TokenStream) -> Args {
     if let Meta::List(list) = attr.interpret_meta().unwrap() {
         let mut args = Args {
             defaults: HashMap::new(),
+            defaults_file: None,
             positional: vec![],
+            args_name: None,
+            builder_name: None,
+            dyn_builder_name: None,
+            macro_name: None,
+            vis: None,
+            attrs: vec![],
+            docs: HashMap::new(),
+            constraints: vec![],
+            into: HashSet::new(),
+            into_all: false,
+            with: HashMap::new(),
+            try_into: HashSet::new(),
+            nullable: HashSet::new(),
+            optional: HashSet::new(),
+            overridable: HashSet::new(),
+            validate: HashMap::new(),
+            map_err: None,
+            ffi: None,
+            safe: false,
+            named_param: None,
+            method: None,
         };
         let mut processed: HashSet<String> = HashSet::new();
+        if let Some(defaults) = defaults {
+            args.defaults = defaults;
+            processed.insert("defaults".to_owned());
+        }
+        for cfg in cfg_defaults {
+            for arg in cfg.defaults {
+                let name = format!("{}", arg.name);
+                let expr = match arg.value {
+                    DefaultValue::Expr(expr) => expr,
+                    _ => panic!("Cannot use defaults_cfg for '{}' - it only takes quoted expressions or `default`", name),
+                };
+                match args.defaults.get_mut(&name) {
+                    Some(default) => default.add_cfg(&name, cfg.predicate.clone(), expr),
+                    None => panic!(
+                        "Cannot use defaults_cfg for '{}' - it needs a default in defaults(..) too, for when the cfg doesn't hold",
+                        name
+                    ),
+                }
+            }
+        }
         for nested in list.nested {
             match nested {
                 NestedMeta::Meta(value) => {
                     let raw_name = format!("{}", &value.name());
+                    if raw_name == "on_struct" || raw_name == "on_fields" {
+                        // These can be repeated, e.g. once per field
+                        args.attrs.push(crate::attrs::parse_meta(&value));
+                        continue;
+                    }
+                    if raw_name == "exclusive" || raw_name == "one_of" || raw_name == "requires" {
+                        // As can these, once per group of parameters
+                        args.constraints.extend(crate::constraints::parse_constraints(&value));
+                        continue;
+                    }
                     if processed.contains(&raw_name) {
                         panic!("Double annotations for {}", raw_name);
                     } else {
                         processed.insert(raw_name.clone());
                     }
-                    if raw_name == "defaults" {
-                        args.defaults = process_defaults(value);
-                    } else if raw_name == "on_struct" {
-                        unimplemented!()
-                    } else if raw_name == "on_fields" {
-                        unimplemented!()
-                    } else if raw_name == "positionals" {
+                    if raw_name == "positionals" {
                         args.positional = process_positionals(value);
+                    } else if raw_name == "into" {
+                        match value {
+                            Meta::Word(_) => args.into_all = true,
+                            value => args.into = process_positionals(value).into_iter().collect(),
+                        }
+                    } else if raw_name == "try_into" {
+                        args.try_into = process_positionals(value).into_iter().collect();
+                    } else if raw_name == "optional" {
+                        args.optional = process_positionals(value).into_iter().collect();
+                    } else if raw_name == "overridable" {
+                        args.overridable = process_positionals(value).into_iter().collect();
+                    } else if raw_name == "nullable" {
+                        args.nullable = process_positionals(value).into_iter().collect();
+                    } else if raw_name == "validate" {
+                        args.validate = process_defaults(value);
+                    } else if raw_name == "defaults_from_file" {
+                        args.defaults_file = Some(extract_lit_str(value));
+                    } else if raw_name == "map_err" {
+                        args.map_err = Some(extract_expr(value));
+                    } else if raw_name == "with" {
+                        args.with = process_defaults(value);
+                    } else if raw_name == "docs" {
+                        for (name, doc) in process_docs(value) {
+                            args.docs.entry(name).or_default().push(doc);
+                        }
+                    } else if raw_name == "args_name" {
+                        args.args_name = Some(extract_ident(value));
+                    } else if raw_name == "builder_name" {
+                        args.builder_name = Some(extract_ident(value));
+                    } else if raw_name == "dyn_builder_name" {
+                        args.dyn_builder_name = Some(extract_ident(value));
+                    } else if raw_name == "macro_name" {
+                        args.macro_name = Some(extract_ident(value));
+                    } else if raw_name == "named_struct" {
+                        args.named_param = Some(NamedParam {
+                            name: extract_ident(value),
+                            attrs: vec![],
+                        });
+                    } else if raw_name == "ffi" {
+                        args.ffi = Some(process_ffi(value));
+                    } else if raw_name == "safe" {
+                        match value {
+                            Meta::Word(_) => args.safe = true,
+                            _ => panic!("Wrong format for safe - it doesn't take any arguments"),
+                        }
+                    } else if raw_name == "vis" {
+                        args.vis = Some(extract_vis(value));
                     } else {
-                        panic!()
+                        panic!("Unknown option '{}'", raw_name)
                     }
                 }
                 _ => panic!("Incorrect macro format! - bad nesting"),
@@ -48,6 +152,33 @@ pub fn parse_args(args: ::proc_macro::TokenStream) -> Args {
     }
 }
 
+/// `ffi`, `ffi = "symbol"` or `ffi(symbol = "symbol", repr_c(Type, ..))`
+fn process_ffi(meta: Meta) -> Ffi {
+    let mut ffi = Ffi::default();
+    match meta {
+        Meta::Word(_) => {}
+        meta @ Meta::NameValue(_) => ffi.symbol = Some(extract_lit_str(meta)),
+        Meta::List(list) => {
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(meta) => {
+                        let name = format!("{}", meta.name());
+                        if name == "symbol" {
+                            ffi.symbol = Some(extract_lit_str(meta));
+                        } else if name == "repr_c" {
+                            ffi.repr_c = process_positionals(meta).into_iter().collect();
+                        } else {
+                            panic!("Unknown ffi option '{}' - expected symbol = \"..\" or repr_c(..)", name);
+                        }
+                    }
+                    _ => panic!("Incorrect macro format! - bad nesting"),
+                }
+            }
+        }
+    }
+    ffi
+}
+
 fn process_positionals(meta: Meta) -> Vec<String> {
     if let Meta::List(list) = meta {
         list.nested
@@ -84,6 +215,105 @@ fn process_defaults(meta: Meta) -> HashMap<String, Expr> {
     }
 }
 
+/// Pulls the `defaults(..)` list out of the macro's arguments, returning it parsed along with the other arguments
+fn split_defaults(args: TokenStream) -> (Option<HashMap<String, DefaultValue>>, Vec<CfgDefaults>, TokenStream) {
+    let mut items: Vec<Vec<TokenTree>> = vec![vec![]];
+    for token in args {
+        match token {
+            TokenTree::Punct(ref punct) if punct.as_char() == ',' => items.push(vec![]),
+            token => items.last_mut().unwrap().push(token),
+        }
+    }
+
+    let mut defaults = None;
+    let mut cfg_defaults = vec![];
+    let mut rest: Vec<TokenStream> = vec![];
+    for item in items.into_iter().filter(|v| !v.is_empty()) {
+        match item.as_slice() {
+            [TokenTree::Ident(ident), TokenTree::Group(group)]
+                if format!("{}", ident) == "defaults" && group.delimiter() == Delimiter::Parenthesis =>
+            {
+                if defaults.is_some() {
+                    panic!("Double annotations for defaults");
+                }
+                let parsed = Punctuated::<DefaultArg, Token![,]>::parse_terminated
+                    .parse2(group.stream())
+                    .unwrap_or_else(|e| panic!("Wrong format for defaults - {}", e));
+                defaults = Some(
+                    parsed
+                        .into_iter()
+                        .map(|arg| (format!("{}", arg.name), arg.value))
+                        .collect(),
+                );
+            }
+            // Can be repeated, once per cfg predicate
+            [TokenTree::Ident(ident), TokenTree::Group(group)]
+                if format!("{}", ident) == "defaults_cfg" && group.delimiter() == Delimiter::Parenthesis =>
+            {
+                cfg_defaults.push(syn::parse2(group.stream()).unwrap_or_else(|e| {
+                    panic!("Wrong format for defaults_cfg. Expected (predicate, name = \"expression\", ..) - {}", e)
+                }));
+            }
+            _ => rest.push(item.into_iter().collect()),
+        }
+    }
+    (defaults, cfg_defaults, quote!(#(#rest),*))
+}
+
+/// `defaults_cfg(predicate, name = "expression", ...)`, for defaults that depend on the compile time configuration
+struct CfgDefaults {
+    predicate: NestedMeta,
+    defaults: Punctuated<DefaultArg, Token![,]>,
+}
+
+impl Parse for CfgDefaults {
+    fn parse(input: ParseStream) -> syn::parse::Result<Self> {
+        let predicate = input.parse()?;
+        input.parse::<Token![,]>()?;
+        Ok(CfgDefaults {
+            predicate,
+            defaults: Punctuated::parse_terminated(input)?,
+        })
+    }
+}
+
+/// A `name = value` entry in `defaults(..)`
+struct DefaultArg {
+    name: Ident,
+    value: DefaultValue,
+}
+
+impl Parse for DefaultArg {
+    fn parse(input: ParseStream) -> syn::parse::Result<Self> {
+        let name: Ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+        Ok(DefaultArg {
+            name,
+            value: input.parse()?,
+        })
+    }
+}
+
+fn process_docs(meta: Meta) -> Vec<(String, String)> {
+    if let Meta::List(list) = meta {
+        list.nested
+            .iter()
+            .map(|value| match value {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    ident,
+                    lit: Lit::Str(lit_str),
+                    ..
+                })) => (format!("{}", ident), lit_str.value()),
+                _ => panic!(
+                    "Wrong format for specifying docs. Expected name = \"docs\", got '{}'",
+                    quote!(#value)
+                ),
+            }).collect()
+    } else {
+        panic!("Cannot parse docs");
+    }
+}
+
 pub fn extract_default(m: Meta) -> (String, Expr) {
     match m {
         Meta::NameValue(MetaNameValue { ident, lit, .. }) => (format!("{}", ident), as_expr(lit)),
@@ -91,6 +321,59 @@ pub fn extract_default(m: Meta) -> (String, Expr) {
     }
 }
 
+fn extract_ident(meta: Meta) -> Ident {
+    match meta {
+        Meta::NameValue(MetaNameValue {
+            lit: Lit::Str(lit_str),
+            ..
+        }) => lit_str.parse::<Ident>().unwrap_or_else(|_| {
+            panic!("Expected an identifier, got '{}'", lit_str.value())
+        }),
+        _ => panic!(
+            "Wrong format for specifying a name. Expected name = \"Ident\", got '{}'",
+            quote!(#meta)
+        ),
+    }
+}
+
+fn extract_lit_str(meta: Meta) -> LitStr {
+    match meta {
+        Meta::NameValue(MetaNameValue {
+            lit: Lit::Str(lit_str),
+            ..
+        }) => lit_str,
+        _ => panic!(
+            "Wrong format. Expected name = \"value\", got '{}'",
+            quote!(#meta)
+        ),
+    }
+}
+
+fn extract_vis(meta: Meta) -> Visibility {
+    match meta {
+        Meta::NameValue(MetaNameValue {
+            lit: Lit::Str(lit_str),
+            ..
+        }) => lit_str.parse::<Visibility>().unwrap_or_else(|_| {
+            panic!("Expected a visibility, got '{}'", lit_str.value())
+        }),
+        _ => panic!(
+            "Wrong format for specifying visibility. Expected vis = \"pub(crate)\", got '{}'",
+            quote!(#meta)
+        ),
+    }
+}
+
+fn extract_expr(meta: Meta) -> Expr {
+    match meta {
+        Meta::NameValue(MetaNameValue { lit, .. }) => as_expr(lit),
+        _ => panic!(
+            "Wrong format. Expected name = \"expression\", got '{}'",
+            quote!(#meta)
+        ),
+    }
+}
+
 pub fn as_expr(lit: Lit) -> Expr {
     if let Lit::Str(lit_str) = lit {
         lit_str.parse::<Expr>().unwrap()
@@ -101,6 +384,43 @@ pub fn as_expr(lit: Lit) -> Expr {
 
 #[derive(Debug)]
 pub struct Args {
-    pub defaults: HashMap<String, Expr>,
+    pub defaults: HashMap<String, DefaultValue>,
+    /// A config file to read the defaults from when the fn is called, before falling back to the declared ones
+    pub defaults_file: Option<LitStr>,
     pub positional: Vec<String>,
+    pub args_name: Option<Ident>,
+    pub builder_name: Option<Ident>,
+    pub dyn_builder_name: Option<Ident>,
+    pub macro_name: Option<Ident>,
+    pub vis: Option<Visibility>,
+    pub attrs: Vec<ParsedAttr>,
+    /// Documentation for each parameter, by name
+    pub docs: HashMap<String, Vec<String>>,
+    pub constraints: Vec<Constraint>,
+    /// Parameters whose setters take `impl Into<T>`
+    pub into: HashSet<String>,
+    /// Whether every parameter's setter takes `impl Into<T>`
+    pub into_all: bool,
+    /// Parameters whose setters run a conversion function
+    pub with: HashMap<String, Expr>,
+    /// Parameters whose setters take `impl TryInto<T>`
+    pub try_into: HashSet<String>,
+    /// `Option` parameters that default to `None`, without being wrapped in another `Option`
+    pub optional: HashSet<String>,
+    /// Defaulted parameters whose default can be overridden for a scope, with `with_defaults`
+    pub overridable: HashSet<String>,
+    /// Defaulted parameters whose type is an `Option` (e.g. through an alias), where `None` is a value
+    pub nullable: HashSet<String>,
+    /// Checks on the arguments, by parameter
+    pub validate: HashMap<String, Expr>,
+    /// Converts an `ArgError` into the function's own error type
+    pub map_err: Option<Expr>,
+    /// Whether to generate an `extern "C"` entry point, taking a `#[repr(C)]` args struct - and how
+    pub ffi: Option<Ffi>,
+    /// Whether the wrapper for a function in an `extern` block is safe to call
+    pub safe: bool,
+    /// The parameter that takes the named arguments as one struct, if they aren't taken one by one
+    pub named_param: Option<NamedParam>,
+    /// The type the fn is a method of, if it's declared in an impl block
+    pub method: Option<Method>,
 }
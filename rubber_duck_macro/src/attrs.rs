@@ -0,0 +1,81 @@
+use quote::quote;
+use syn::{ItemFn, Meta, NestedMeta};
+
+/// Drains any `#[for_struct(..)]` and `#[for_field(name, ..)]` attributes from the fn,
+/// so they can be piggy-backed onto the generated args struct instead
+pub fn parse_attrs(fn_item: &mut ItemFn) -> Vec<ParsedAttr> {
+    crate::util::drain_map(&mut fn_item.attrs, |attr| {
+        let name = attr.path.segments.iter().last().map(|v| format!("{}", v.ident));
+        match name.as_deref() {
+            Some("for_field") | Some("for_struct") => {
+                let meta = attr.interpret_meta();
+                if let Some(meta) = meta {
+                    Some(parse_meta(&meta))
+                } else {
+                    panic!("Not a parseable meta: {}", quote!(#attr));
+                }
+            }
+            _ => None,
+        }
+    })
+}
+
+#[derive(Debug)]
+pub enum AttrLocation {
+    Struct,
+    Field(String),
+}
+
+#[derive(Debug)]
+pub struct ParsedAttr {
+    pub location: AttrLocation,
+    pub content: Vec<Meta>,
+}
+
+/// Parses the contents of either a `for_field`/`on_fields` or a `for_struct`/`on_struct` list
+pub fn parse_meta(meta: &Meta) -> ParsedAttr {
+    match meta {
+        Meta::List(meta) => {
+            let name = format!("{}", meta.ident);
+            if "for_field" == name || "on_fields" == name {
+                let mut iter = meta.nested.iter();
+                let field_name = match iter.next() {
+                    Some(NestedMeta::Literal(syn::Lit::Str(first))) => first.value(),
+                    Some(NestedMeta::Meta(Meta::Word(ident))) => format!("{}", ident),
+                    Some(_) => panic!("Expected a string literal or single identifier"),
+                    None => panic!("Not enough values passed to '{}'", name),
+                };
+                let content = parse_contents(iter);
+                if content.is_empty() {
+                    panic!("Not enough items in attribute list: {}", quote!(#meta));
+                }
+                ParsedAttr {
+                    location: AttrLocation::Field(field_name),
+                    content,
+                }
+            } else if "for_struct" == name || "on_struct" == name {
+                let content = parse_contents(meta.nested.iter());
+                if content.is_empty() {
+                    panic!("Not enough items in the attribute list: {}", quote!(#meta));
+                }
+                ParsedAttr {
+                    location: AttrLocation::Struct,
+                    content,
+                }
+            } else {
+                panic!("Unknown attribute! {}", &meta.ident);
+            }
+        }
+        _ => panic!("Expected a list of attributes, got: {}", quote!(#meta)),
+    }
+}
+
+fn parse_contents<'a>(iter: impl Iterator<Item = &'a NestedMeta>) -> Vec<Meta> {
+    iter.map(|nested| match nested {
+        NestedMeta::Literal(_) => panic!(
+            "Cannot use a string literal for the annotation: {}",
+            quote!(#nested)
+        ),
+        NestedMeta::Meta(nested_meta) => nested_meta.clone(),
+    }).collect()
+}
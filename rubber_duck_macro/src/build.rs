@@ -3,21 +3,22 @@ use ::syn::parse_quote;
 use proc_macro2::Ident;
 use proc_macro2::Span;
 use quote::quote;
-use syn::ItemFn;
-use crate::parse_fn::FieldRole;
+use syn::{ItemFn, ReturnType};
 
-pub fn gen_builder(structure: &parse_fn::Structure) -> ::proc_macro2::TokenStream {
+pub fn gen_builder(structure: &parse_fn::Structure, output: &ReturnType) -> ::proc_macro2::TokenStream {
 
-    crate::builder::create_typesafe_builder(structure)
+    crate::builder::create_typesafe_builder(structure, output)
 }
 
 pub fn gen_macro(structure: &parse_fn::Structure) -> ::proc_macro2::TokenStream {
-    let field_names = structure.names();
-    let name = &structure.ident;
-    let struct_name = Ident::new(
-        &crate::util::uppercase(&format!("{}", &structure.ident)),
-        Span::call_site(),
+    let call_args = crate::named_struct::call_args(
+        structure,
+        structure.names().iter().map(|name| quote!(__temp.#name)).collect(),
     );
+    let name = &structure.ident;
+    let macro_name = &structure.names.macro_name;
+    let struct_name = &structure.names.args;
+    let vis = &structure.vis;
 
     //    println!("{}", quote!($test));
 
@@ -32,18 +33,21 @@ pub fn gen_macro(structure: &parse_fn::Structure) -> ::proc_macro2::TokenStream
         res
     });
 
-    let doc_string = format!("Executes [{}](fn.{}.html) with name paramters as appropriate", &name, &name);
+    let doc_string = format!(
+        "Executes [{}](fn.{}.html) with named parameters as appropriate\n\n{}",
+        &name,
+        &name,
+        crate::doc::doc_section(structure)
+    );
 
     let quoted = quote!(
         #[doc = #doc_string]
-        pub macro #name(#(#p_expr_matchers,)* $($names:ident => $value:expr),*) {{
+        #vis macro #macro_name(#(#p_expr_matchers,)* $($names:ident => $value:expr),*) {{
             let __temp = #struct_name::builder()
                     #(#p_expr_expanders)*
                     $(.$names($value))*
                     .build();
-                #name(
-                    #(__temp.#field_names,)*
-                )
+                #name(#call_args)
         }}
     );
 
@@ -53,21 +57,28 @@ pub fn gen_macro(structure: &parse_fn::Structure) -> ::proc_macro2::TokenStream
 pub fn mod_block_add_defaults(fn_item: &mut ItemFn, structure: &parse_fn::Structure) {
     let statements = fn_item.block.stmts.clone();
     let has_defaults: Vec<_> = structure
-        .named()
+        .fields
         .iter()
-        .filter(|&f| f.extra.default.is_some())
-        .map(|v| v.clone())
+        .filter(|f| f.has_default() && !f.is_optional())
         .collect();
     let names1 = has_defaults.iter().map(|v| &v.name);
     let names2 = has_defaults.iter().map(|v| &v.name);
-    let expr = has_defaults.iter().map(|v| v.extra.default.as_ref().unwrap());
+    crate::validate::check_try_defaults(fn_item, structure);
+    let (validations, map_err) = crate::validate::gen_checks(fn_item, structure);
+    let (checks, flag_checks) = crate::constraints::runtime_checks(structure, map_err.as_ref());
+    let expr = has_defaults
+        .iter()
+        .map(|f| crate::defaults::gen_default(structure, f, map_err.as_ref()));
     let block = parse_quote!(
       {
+          #checks
           #(let #names1 = if let Some(__var) = #names2{
                __var
            } else {
                #expr
            };)*
+          #flag_checks
+          #validations
           #(#statements)*
       }
   );
@@ -1,17 +1,19 @@
-use quote::quote;
+use quote::{quote, quote_spanned};
 use syn::export::Span;
 use syn::export::ToTokens;
 use syn::AngleBracketedGenericArguments;
 use syn::PathSegment;
+use syn::spanned::Spanned;
 use syn::{
     Expr, Field, Fields, GenericArgument, Ident, Item, Lit, Meta, MetaNameValue,
-    PathArguments, Type,
+    PathArguments, ReturnType, Type,
 };
 use crate::parse_fn::Structure;
 use crate::parse_fn::FieldRole;
+use crate::parse_fn::Conversion;
 use proc_macro2::TokenStream;
 
-enum IsOption {
+pub enum IsOption {
     True(Type),
     False,
 }
@@ -24,48 +26,116 @@ fn extract_option_type(wrapped_type: &AngleBracketedGenericArguments) -> Type {
     }
 }
 
-fn get_option_type(ty: Type) -> IsOption {
+/// Whether the type is an `Option`, by any of the paths it's usually written with.
+/// Aliases can't be seen through, which is what `nullable(..)` is for
+pub fn get_option_type(ty: Type) -> IsOption {
     match ty {
-        Type::Path(ty) => match ty.path.segments.iter().next() {
-            Some(pp) => {
-                let PathSegment {
-                    ident,
-                    ref arguments,
-                } = pp;
-                if format!("{}", &ident) == "Option" {
+        Type::Path(ty) => {
+            let segments: Vec<&PathSegment> = ty.path.segments.iter().collect();
+            let prefix: Vec<String> = segments
+                .iter()
+                .take(segments.len().saturating_sub(1))
+                .map(|v| format!("{}", v.ident))
+                .collect();
+            let std_prefix = match prefix.as_slice() {
+                [] => ty.path.leading_colon.is_none(),
+                [krate, module] => (krate == "std" || krate == "core") && module == "option",
+                _ => false,
+            };
+            match segments.last() {
+                Some(PathSegment { ident, ref arguments })
+                    if std_prefix && ty.qself.is_none() && format!("{}", &ident) == "Option" =>
+                {
                     IsOption::True(match arguments {
                         PathArguments::AngleBracketed(args) => extract_option_type(args),
                         _ => panic!("Can't handle this type of option"),
                     })
-                } else {
-                    IsOption::False
                 }
+                _ => IsOption::False,
             }
-            _ => IsOption::False,
-        },
+        }
         _ => IsOption::False,
     }
 }
 
-pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStream {
-    let struct_name = Ident::new(
-        &crate::util::uppercase(&format!("{}", &structure.ident)),
-        structure.ident.span(),
-    );
+/// The type that calling the fn gives back - the future, for an async fn
+pub fn output_type(structure: &Structure, output: &ReturnType) -> TokenStream {
+    let output_ty = match output {
+        ReturnType::Type(_, ty) => quote!(#ty),
+        ReturnType::Default => quote!(()),
+    };
+    if structure.is_async {
+        quote!(impl ::std::future::Future<Output = #output_ty>)
+    } else {
+        output_ty
+    }
+}
+
+/// The types the builder starts out with - defaulted fields start as their option, unless they're tracked
+/// for constraints, and the rest are `Unset`
+pub fn initial_types(structure: &Structure) -> Vec<TokenStream> {
+    structure.fields.iter().map(|v| {
+        if v.has_default() && !structure.is_tracked(v) {
+            let ty = &v.ty;
+            quote!(#ty)
+        } else {
+            quote!(Unset)
+        }
+    }).collect()
+}
+
+/// The `unsafe` for the methods that call an `unsafe fn`, along with a doc line saying so
+pub fn unsafety(structure: &Structure) -> (TokenStream, TokenStream) {
+    if structure.is_unsafe {
+        let doc = format!(
+            "\n\n# Safety\n\nThis calls {}, so the caller has to uphold its safety requirements",
+            crate::doc::fn_link(structure)
+        );
+        (quote!(unsafe), quote!(#[doc = #doc]))
+    } else {
+        (quote!(), quote!())
+    }
+}
+
+pub fn create_typesafe_builder(structure: &Structure, output: &ReturnType) -> proc_macro2::TokenStream {
+    let struct_name = &structure.names.args;
+    let vis = &structure.vis;
+    // The args struct is generic over the types of the `impl Trait` parameters - the builders are made through it
+    // with each `Unset`, as nothing says what they are yet
+    let generics = structure.generic_args(false);
+    let unset_generics = structure.generic_args(true);
+    let type_params = structure.type_params();
+    let (generics, unset_generics, type_params) = (&generics, &unset_generics, &type_params);
+    // The builder of a const fn can be used in const contexts, e.g. by `n!` in a static's initializer
+    let constness = if structure.is_const { quote!(const) } else { quote!() };
+    let constness = &constness;
+
+    let hidden = |custom: bool| if custom {
+        quote!()
+    } else {
+        quote!(#[doc(hidden)])
+    };
 
     // struct Base
     let struct_decl = {
         let field_decs = structure.fields.iter().map(|f| {
             let name = &f.name;
             let ty = &f.ty;
+            let attrs = &f.attrs;
+            let docs = &f.docs;
 
             quote!(
-                pub #name: #ty,
+                #(#[doc = #docs])*
+                #(#[#attrs])*
+                #vis #name: #ty,
             )
         });
+        let hidden = hidden(structure.names.custom_args);
+        let attrs = &structure.attrs;
         quote!(
-            #[doc(hidden)]
-            pub struct #struct_name {
+            #hidden
+            #(#[#attrs])*
+            #vis struct #struct_name#generics {
                 #(#field_decs)*
             }
         )
@@ -73,6 +143,32 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
 
     let mut parts = vec![struct_decl];
 
+    // The n! macro looks up the builder through the fn's name, so alias the struct if it was renamed. It
+    // finds a method's builder through the method instead
+    if struct_name != &structure.ident && structure.method.is_none() {
+        let fn_name = &structure.ident;
+        parts.push(quote!(
+            #[doc(hidden)]
+            #[allow(non_camel_case_types)]
+            #vis type #fn_name#generics = #struct_name#generics;
+        ));
+    }
+
+    // The setters of a const fn's builder copy the fields it keeps (see below), so each parameter's type has to be
+    // `Copy` - checked here, so the error points at the parameter
+    if structure.is_const {
+        let checks = structure.fields.iter().map(|f| {
+            let ty = &f.declared_ty;
+            quote_spanned!(ty.span()=> assert_copy::<#ty>();)
+        });
+        parts.push(quote!(
+            const _: fn() = || {
+                fn assert_copy<T: Copy>() {}
+                #(#checks)*
+            };
+        ));
+    }
+
     let unset = Ident::new("Unset", Span::call_site());
 
     let base_types = || -> Vec<TokenStream> {
@@ -83,8 +179,7 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
             .collect()
     };
 
-    let struct_name = &structure.ident;
-    let builder_name = Ident::new(&format!("{}Builder", &struct_name), Span::call_site());
+    let builder_name = &structure.names.builder;
     let value = Ident::new("value", Span::call_site());
 
     // struct Builder
@@ -93,11 +188,15 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
         let field_types =  structure.fields.iter().map(|v| &v.name);
         let struct_types =  structure.fields.iter().map(|v| &v.name);
 
+        let hidden = hidden(structure.names.custom_builder);
+        let doc = crate::doc::doc_attr(structure);
+
         quote!(
             #[allow(non_snake_case)]
             #[allow(non_camel_case_types)]
-            #[doc(hidden)]
-            pub struct #builder_name<#(#struct_types),*>{
+            #hidden
+            #doc
+            #vis struct #builder_name<#(#struct_types),*>{
               #(#idents: #field_types,)*
             }
           )
@@ -105,19 +204,12 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
 
     // impl Plain Struct builder() -> Builder
     parts.push({
-        let field_types =  structure.fields.iter().map(|v| {
-            if v.has_default() {
-                let ty = &v.ty;
-                quote!(#ty)
-            } else {
-                quote!(#unset)
-            }
-        });
+        let field_types = initial_types(structure);
 
         quote!(
-            impl #struct_name {
+            impl #struct_name#unset_generics {
               #[allow(non_camel_case_types)]
-              pub fn builder() -> #builder_name<#(#field_types),*> {
+              #vis #constness fn builder() -> #builder_name<#(#field_types),*> {
                 #builder_name::new()
               }
             }
@@ -126,19 +218,14 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
 
     // impl Builder new() -> Builder
     parts.push({
-        let field_types = structure.fields.iter().map(|v| {
-            if v.has_default() {
-                let ty = &v.ty;
-                quote!(#ty)
-            } else {
-                quote!(#unset)
-            }
-        });
+        let field_types = initial_types(structure);
         let unsets = structure.fields.iter().map(|_v| &unset);
         let field_decs = structure.fields.iter().map(|f| {
             let ident = &f.name;
-            if let Some(ref default_value) = f.default_expr() {
-                quote!(#ident : Some(#default_value),)
+            if structure.is_tracked(f) {
+                quote!(#ident : #unset,)
+            } else if let Some(initial) = initial_value(structure, f) {
+                quote!(#ident : #initial,)
             } else {
                 quote!(#ident : #unset,)
             }
@@ -146,7 +233,7 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
         quote!(
           impl #builder_name<#(#unsets),*>{
             #[allow(non_camel_case_types)]
-            pub fn new() -> #builder_name<#(#field_types),*> {
+            #vis #constness fn new() -> #builder_name<#(#field_types),*> {
               #builder_name {
                 #(#field_decs)*
               }
@@ -164,6 +251,11 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
             let mut impl_types = base_types();
             if field.is_positional() {
                 impl_types = impl_types.into_iter().skip(idx+1).collect();
+                // The earlier positional fields are set, so their types are the struct's type parameters
+                impl_types.extend(structure.fields[..idx].iter().filter_map(|f| structure.type_param(&f.name)).map(|param| {
+                    let param = &param.ident;
+                    quote!(#param)
+                }));
             } else {
                 if !field.has_default(){
                     impl_types.remove(idx); // Corresponds to the else block in the struct types map if statement
@@ -207,65 +299,134 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
                 &field.name
             };
 
-            let value_type = &field.ty;
-
-            let field_names = structure.fields.iter().map(|v| &v.name);
-            let assignments = structure.fields.iter().enumerate().map(|(i, v)|
-                if i == idx {
-                    quote!(#value)
-                } else {
-                    let n = &v.name;
-                    quote!(self.#n)
-                });
-
-            let option_type = get_option_type(value_type.clone());
-
-            match option_type {
-                IsOption::False => {
-                    quote!(
-                            #[allow(non_camel_case_types)]
-                            impl <#(#impl_types),*> #builder_name<#(#struct_types),*> {
-                                pub fn #fn_name(self, value: #value_type) -> #builder_name<#(#fn_types),*> {
-                                  #builder_name {
-                                    #(#field_names : #assignments,)*
-                                  }
-                                }
-                            }
-                            )
-                },
-                IsOption::True(wrapped) => {
-                    quote!(
-                            #[allow(non_camel_case_types)]
-                            impl <#(#impl_types),*> #builder_name<#(#struct_types),*> {
-                                pub fn #fn_name<VALUE: crate::AsOption<#wrapped>>(self, value: VALUE) -> #builder_name<#(#fn_types),*> {
-                                  let value : #value_type = value.as_option();
-                                  #builder_name {
-                                    #(#field_names : #assignments,)*
-                                  }
-                                }
-                            }
-                          )
-                },
+            let fn_types = &fn_types;
+            let setter = |fn_name: &Ident, docs: Vec<String>, (generics, param_type, convert): (TokenStream, TokenStream, TokenStream)| {
+                let field_names = structure.fields.iter().map(|v| &v.name);
+                let assignments = structure.fields.iter().enumerate().map(|(i, v)|
+                    if i == idx {
+                        quote!(#value)
+                    } else {
+                        let n = &v.name;
+                        quote!(self.#n)
+                    });
+                quote!(
+                    #(#[doc = #docs])*
+                    #vis #constness fn #fn_name#generics(self, value: #param_type) -> #builder_name<#(#fn_types),*> {
+                      #convert
+                      #builder_name {
+                        #(#field_names : #assignments,)*
+                      }
+                    }
+                )
+            };
+
+            let conversion = match structure.type_param(&field.name) {
+                // The setter of an `impl Trait` parameter is generic, and the builder takes on the type it's given
+                Some(param) => {
+                    let ty = &field.ty;
+                    (quote!(<#param>), quote!(#ty), quote!())
+                }
+                None if structure.is_const => const_setter_conversion(field),
+                None => setter_conversion(field),
+            };
+            let mut setters = vec![setter(fn_name, crate::doc::setter_docs(structure, field, false), conversion)];
+            if field.has_default() {
+                setters.push(setter(
+                    &maybe_setter_name(field),
+                    crate::doc::maybe_setter_docs(field),
+                    maybe_setter_conversion(field),
+                ));
             }
+
+            // A const fn can't drop the builder while its field types are generic, even once every field has been
+            // moved out of it - unless they're `Copy`, and so have nothing to drop
+            let bounds = if structure.is_const {
+                let impl_types = &impl_types;
+                quote!(where #(#impl_types: Copy),*)
+            } else {
+                quote!()
+            };
+            quote!(
+                #[allow(non_camel_case_types)]
+                impl <#(#impl_types),*> #builder_name<#(#struct_types),*> #bounds {
+                    #(#setters)*
+                }
+            )
         }).collect::<Vec<_>>();
 
     parts.append(&mut quoted_impls);
 
 
-    // impl Builder Struct build() -> Plain
+    // Fields tracked for constraints are generic, and only become their option once built
+    let tracked_types = structure.fields.iter().filter(|v| structure.is_tracked(v)).map(|v| &v.name).collect::<Vec<_>>();
+    let built_types = structure.fields.iter().map(|v| if structure.is_tracked(v) {
+        let name = &v.name;
+        quote!(#name)
+    } else {
+        let ty = &v.ty;
+        quote!(#ty)
+    }).collect::<Vec<_>>();
+    let built_values = structure.fields.iter().map(|v| {
+        let name = &v.name;
+        if structure.is_tracked(v) {
+            quote!(crate::MaybeSet::into_option(self.#name))
+        } else {
+            quote!(self.#name)
+        }
+    }).collect::<Vec<_>>();
+    let where_clauses = crate::constraints::where_clauses(structure);
+
+    parts.push(crate::constraints::gen_traits(structure));
+
+    // impl Builder Struct build() -> Plain, and call()
     parts.push({
         let field_names = structure.fields.iter().map(|v| &v.name);
-        let field_names_2 = structure.fields.iter().map(|v| &v.name);
-        let struct_types = structure.fields.iter().map(|v| &v.ty);
+        let call_args = crate::named_struct::call_args(
+            structure,
+            structure.fields.iter().map(|f| {
+                let name = &f.name;
+                quote!(args.#name)
+            }).collect(),
+        );
+        let (receiver, call) = crate::method::call(structure, &call_args);
+        let receiver_arg = if structure.method.is_some() { quote!(receiver) } else { quote!() };
+        let output_ty = output_type(structure, output);
+        let (unsafety, call_doc) = unsafety(structure);
+        let (tracked_types, built_types, built_values, where_clauses) =
+            (&tracked_types, &built_types, &built_values, &where_clauses);
+
+        // A parameter named `call` has a setter of that name, so its builder is finished with `build().call()`
+        let builder_call = if structure.fields.iter().any(|f| f.name == "call") {
+            quote!()
+        } else {
+            quote!(
+                /// Builds the arguments and calls the function with them
+                #call_doc
+                #vis #constness #unsafety fn call(self #receiver) -> #output_ty {
+                  self.build().call(#receiver_arg)
+                }
+            )
+        };
 
         quote!(
           #[allow(non_camel_case_types)]
-          impl #builder_name<#(#struct_types),*>{
-            pub fn build(self) -> #struct_name {
+          impl <#(#tracked_types,)* #(#type_params),*> #builder_name<#(#built_types),*> where #(#where_clauses,)* {
+            #vis #constness fn build(self) -> #struct_name#generics {
               #struct_name {
-                #(#field_names : self.#field_names_2, )*
+                #(#field_names : #built_values, )*
               }
             }
+
+            #builder_call
+          }
+
+          impl <#(#type_params),*> #struct_name#generics {
+            /// Calls the function with the arguments
+            #call_doc
+            #vis #constness #unsafety fn call(self #receiver) -> #output_ty {
+              let args = self;
+              #call
+            }
           }
         )
     });
@@ -273,17 +434,17 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
 
     // impl Deconstruct<Args> for Builder
     parts.push({
-        let field_names = structure.fields.iter().map(|v| &v.name);
         let struct_types_generic = structure.fields.iter().map(|v| &v.ty);
-        let struct_types_struct = structure.fields.iter().map(|v| &v.ty);
         let struct_types_return = structure.fields.iter().map(|v| &v.ty);
+        let (tracked_types, built_types, built_values, where_clauses) =
+            (&tracked_types, &built_types, &built_values, &where_clauses);
 
         quote!(
           #[allow(non_camel_case_types)]
-          impl crate::Deconstruct<(#(#struct_types_generic,)*)> for #builder_name<#(#struct_types_struct),*>{
-            fn deconstruct(self) -> (#(#struct_types_return),*) {
+          impl <#(#tracked_types,)* #(#type_params),*> crate::Deconstruct<(#(#struct_types_generic,)*)> for #builder_name<#(#built_types),*> where #(#where_clauses,)* {
+            fn deconstruct(self) -> (#(#struct_types_return,)*) {
               (
-                #(self.#field_names,)*
+                #(#built_values,)*
               )
             }
           }
@@ -292,3 +453,147 @@ pub fn create_typesafe_builder(structure: &Structure) -> proc_macro2::TokenStrea
 
     quote!(#(#parts)*)
 }
+
+/// The value a builder starts out with for a defaulted field, before any setter is called
+pub fn initial_value(structure: &Structure, field: &crate::parse_fn::Field<FieldRole>) -> Option<TokenStream> {
+    match field.default_value() {
+        // The default is applied in the fn instead, as it isn't a `Result`
+        Some(_) if field.is_try_into() => Some(quote!(None)),
+        // The parameter is already an `Option`, with `None` as its default
+        Some(_) if field.is_optional() => Some(quote!(None)),
+        // The default is made in the fn instead, when it's called
+        Some(_) if crate::defaults::is_deferred(structure, field) => Some(quote!(None)),
+        Some(default_value) => {
+            let default_value = default_value.fixed_expr();
+            Some(quote!(Some(#default_value)))
+        }
+        None => None,
+    }
+}
+
+/// The setter's generics, the type it takes, and how that's turned into the field's type (as `value`)
+pub fn setter_conversion(field: &crate::parse_fn::Field<FieldRole>) -> (TokenStream, TokenStream, TokenStream) {
+    let value_type = &field.ty;
+    let option_type = get_option_type(value_type.clone());
+
+    match (option_type, &field.conversion) {
+        // `None` is a value for a nullable parameter, so the setter always overrides the default
+        (_, None) | (_, Some(Conversion::Into)) if field.nullable => {
+            let declared = &field.declared_ty;
+            (
+                quote!(<VALUE: Into<#declared>>),
+                quote!(VALUE),
+                quote!(let value : #value_type = Some(value.into());),
+            )
+        },
+        (IsOption::False, None) => (
+            quote!(),
+            quote!(#value_type),
+            quote!(),
+        ),
+        (IsOption::True(wrapped), None) => (
+            quote!(<VALUE: crate::AsOption<#wrapped>>),
+            quote!(VALUE),
+            quote!(let value : #value_type = value.as_option();),
+        ),
+        (IsOption::False, Some(Conversion::Into)) => (
+            quote!(<VALUE: Into<#value_type>>),
+            quote!(VALUE),
+            quote!(let value : #value_type = value.into();),
+        ),
+        (IsOption::True(wrapped), Some(Conversion::Into)) => (
+            quote!(<VALUE: crate::IntoOptionOf<#wrapped, MARKER>, MARKER>),
+            quote!(VALUE),
+            quote!(let value : #value_type = value.into_option_of();),
+        ),
+        (IsOption::False, Some(Conversion::With { func, input })) => (
+            quote!(),
+            quote!(#input),
+            quote!(let value : #value_type = (#func)(value);),
+        ),
+        (IsOption::True(_), Some(Conversion::With { func, input })) => (
+            quote!(),
+            quote!(#input),
+            quote!(let value : #value_type = Some((#func)(value));),
+        ),
+        (option_type, Some(Conversion::TryInto)) => {
+            let declared = &field.declared_ty;
+            let param = format!("{}", &field.name);
+            let converted = quote!(
+                ::std::convert::TryInto::try_into(value)
+                    .map_err(|e: ERROR| crate::ArgError::new(#param, e.to_string()))
+            );
+            let converted = match option_type {
+                IsOption::True(_) => quote!(Some(#converted)),
+                IsOption::False => converted,
+            };
+            (
+                quote!(<VALUE: ::std::convert::TryInto<#declared, Error = ERROR>, ERROR: ::std::fmt::Display>),
+                quote!(VALUE),
+                quote!(let value : #value_type = #converted;),
+            )
+        },
+    }
+}
+
+/// Like `setter_conversion`, but without going through traits, so the setter can be a const fn. Options are
+/// set by their wrapped value, as `AsOption` can't be used
+fn const_setter_conversion(field: &crate::parse_fn::Field<FieldRole>) -> (TokenStream, TokenStream, TokenStream) {
+    let value_type = &field.ty;
+    match get_option_type(value_type.clone()) {
+        IsOption::True(wrapped) => (quote!(), quote!(#wrapped), quote!(let value : #value_type = Some(value);)),
+        IsOption::False => (quote!(), quote!(#value_type), quote!()),
+    }
+}
+
+/// The name of the setter that takes an option, to maybe override the default, e.g. `n!(f{ {name?: value} })`
+pub fn maybe_setter_name(field: &crate::parse_fn::Field<FieldRole>) -> Ident {
+    Ident::new(&format!("maybe_{}", &field.name), field.name.span())
+}
+
+/// Like `setter_conversion`, but for the `maybe_` setter of a defaulted field - it takes an option
+/// of the declared type, where `None` leaves the field to its default
+pub fn maybe_setter_conversion(field: &crate::parse_fn::Field<FieldRole>) -> (TokenStream, TokenStream, TokenStream) {
+    let value_type = &field.ty;
+    let declared = &field.declared_ty;
+    if field.is_optional() {
+        return (quote!(), quote!(#value_type), quote!());
+    }
+    let convert = if field.is_try_into() {
+        quote!(let value : #value_type = value.map(Ok);)
+    } else {
+        quote!(let value : #value_type = value;)
+    };
+    (quote!(), quote!(Option<#declared>), convert)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_option(ty: &str) -> bool {
+        match get_option_type(syn::parse_str(ty).unwrap()) {
+            IsOption::True(_) => true,
+            IsOption::False => false,
+        }
+    }
+
+    #[test]
+    fn detects_option_paths() {
+        assert!(is_option("Option<u32>"));
+        assert!(is_option("std::option::Option<u32>"));
+        assert!(is_option("::core::option::Option<u32>"));
+        assert!(!is_option("::Option<u32>"));
+        assert!(!is_option("my::Option<u32>"));
+        assert!(!is_option("Result<Option<u32>, ()>"));
+        assert!(!is_option("MaybeLabel"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot name a parameter 'maybe_limit' - its setter would clash with the maybe_ setter of 'limit'")]
+    fn rejects_clashing_maybe_setters() {
+        let mut args = crate::args::parse_args(syn::parse_str(r#"defaults(limit = "10")"#).unwrap());
+        let mut fn_item: syn::ItemFn = syn::parse_str("fn list(limit: u32, maybe_limit: bool) {}").unwrap();
+        crate::parse_fn::parse_field_decl(&mut args, &mut fn_item);
+    }
+}
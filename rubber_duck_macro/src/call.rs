@@ -20,6 +20,8 @@ struct NamedArgCall {
 struct NamedField {
     pub ident: Ident,
     pub expr: Expr,
+    /// `name?: value`, where `value` is an `Option` that only overrides the default if it's `Some`
+    pub maybe: bool,
 }
 
 struct ExpressionList {
@@ -31,12 +33,21 @@ impl Parse for NamedField {
     fn parse(input: ParseStream) -> Result<Self> {
         eprintln!("Parsing named field");
         let ident = input.parse()?;
-        if input.peek(Token![:]) {
+        if input.peek(Token![?]) {
+            input.parse::<Token![?]>()?;
+            input.parse::<Token![:]>()?;
+            Ok(NamedField {
+                ident,
+                expr: input.parse()?,
+                maybe: true,
+            })
+        } else if input.peek(Token![:]) {
             eprintln!("Found a colon");
             input.parse::<Token![:]>()?;
             Ok(NamedField {
                 ident,
                 expr: input.parse()?,
+                maybe: false,
             })
         } else {
             eprintln!("No colon found");
@@ -44,7 +55,7 @@ impl Parse for NamedField {
                 let id = &ident;
                 parse_quote!(#id)
             };
-            Ok( NamedField { ident, expr, })
+            Ok( NamedField { ident, expr, maybe: false })
         }
     }
 }
@@ -68,7 +79,10 @@ impl Parse for ParsingPositional {
                 braced_content.peek2(Token![:]),
                 braced_content.peek2(Token![,]),
                 );
-            if braced_content.peek(Ident) && (braced_content.peek2(Token![:]) || braced_content.peek2(Token![,])) {
+            // An empty `{}` is an empty set of named args, as in `n!(f{a, {}})`, rather than a positional `()`
+            if braced_content.is_empty() {
+                Ok(ParsingPositional::NamedArgs(Punctuated::new()))
+            } else if braced_content.peek(Ident) && (braced_content.peek2(Token![:]) || braced_content.peek2(Token![,]) || braced_content.peek2(Token![?])) {
                 Ok(ParsingPositional::NamedArgs(
                     braced_content.parse_terminated(NamedField::parse)?
                 ))
@@ -129,7 +143,30 @@ impl Parse for NamedArgCall {
     }
 }
 
+/// Splits `receiver.method{..}` into the receiver and `method{..}`, for a call to a method
+fn split_receiver(
+    input: proc_macro2::TokenStream,
+) -> Option<(proc_macro2::TokenStream, Ident, proc_macro2::TokenStream)> {
+    use proc_macro2::{Delimiter, TokenTree};
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    if tokens.len() < 4 {
+        return None;
+    }
+    let (receiver, call) = tokens.split_at(tokens.len() - 3);
+    match call {
+        [TokenTree::Punct(dot), TokenTree::Ident(method), TokenTree::Group(args)]
+            if dot.as_char() == '.' && args.delimiter() == Delimiter::Brace =>
+        {
+            Some((receiver.iter().cloned().collect(), method.clone(), call[1..].iter().cloned().collect()))
+        }
+        _ => None,
+    }
+}
+
 pub fn n(input: TokenStream) -> TokenStream {
+    if let Some((receiver, method, call)) = split_receiver(input.clone().into()) {
+        return n_method(receiver, &method, call);
+    }
     let named_arg_call : NamedArgCall = parse_macro_input!(input as NamedArgCall);
     eprintln!("Done parsing stream");
     let call = &named_arg_call.ty;
@@ -137,17 +174,51 @@ pub fn n(input: TokenStream) -> TokenStream {
         quote!(.next(#expr))
     });
     let name_args = named_arg_call.named_fields.iter().map(|named| {
-        let name = &named.ident;
+        let name = if named.maybe {
+            Ident::new(&format!("maybe_{}", &named.ident), named.ident.span())
+        } else {
+            named.ident.clone()
+        };
         let expr = &named.expr;
         quote!(.#name(#expr))
     });
-    quote!({
-        use $crate::{Deconstruct, Call};
-        let mut built = #call::builder()
+    // Called through the arguments' own `call()`, not the builder's, which a parameter named `call` replaces with its
+    // setter. This used to deconstruct the arguments and `Call::apply` the fn to them, but `n!` only sees the fn's
+    // path - it can't tell a const fn (where traits can't be used) from any other, so every fn is called this way.
+    // `Call` was never implemented for unsafe fns or fns taking a struct of named parameters either
+    quote!(
+        #call::builder()
             #(#pos_args)*
-            #(#name_args)*;
-        let deco = built.deconstruct();
-        #call.apply(deco)
-        }
+            #(#name_args)*
+            .build()
+            .call()
     ).into()
-}
\ No newline at end of file
+}
+/// Calls a method through its hidden twin, which gives the closure the builder - as the builder's type can't be
+/// named from the receiver. The closure is boxed, so a trait's twin can take it through a trait object
+fn n_method(receiver: proc_macro2::TokenStream, method: &Ident, call: proc_macro2::TokenStream) -> TokenStream {
+    let named_arg_call: NamedArgCall = match syn::parse2(call) {
+        Ok(call) => call,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let method = crate::method::trampoline_name(method);
+    let pos_args = named_arg_call.pos_fields.iter().map(|expr| {
+        quote!(.next(#expr))
+    });
+    let name_args = named_arg_call.named_fields.iter().map(|named| {
+        let name = if named.maybe {
+            Ident::new(&format!("maybe_{}", &named.ident), named.ident.span())
+        } else {
+            named.ident.clone()
+        };
+        let expr = &named.expr;
+        quote!(.#name(#expr))
+    });
+    quote!(
+        (#receiver).#method(::std::boxed::Box::new(|builder| builder
+            #(#pos_args)*
+            #(#name_args)*
+            .build()
+        ))
+    ).into()
+}
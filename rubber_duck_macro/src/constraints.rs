@@ -0,0 +1,341 @@
+use crate::builder::{get_option_type, IsOption};
+use crate::parse_fn::Structure;
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use syn::{Lit, Meta, MetaNameValue, NestedMeta};
+
+/// A declared relationship between named arguments, checked against which of them were set
+#[derive(Clone, Debug)]
+pub enum Constraint {
+    /// At most one of the arguments can be set
+    Exclusive(Vec<Ident>),
+    /// Exactly one of the arguments must be set
+    OneOf(Vec<Ident>),
+    /// If the first argument is set, the second must be too
+    Requires(Ident, Ident),
+}
+
+impl Constraint {
+    pub fn fields(&self) -> Vec<&Ident> {
+        match self {
+            Constraint::Exclusive(fields) | Constraint::OneOf(fields) => fields.iter().collect(),
+            Constraint::Requires(first, second) => vec![first, second],
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Constraint::Exclusive(_) => "exclusive",
+            Constraint::OneOf(_) => "one_of",
+            Constraint::Requires(_, _) => "requires",
+        }
+    }
+
+    /// Whether the constraint holds, given which of its fields (in order) were set
+    fn allows(&self, set: &[bool]) -> bool {
+        let count = set.iter().filter(|v| **v).count();
+        match self {
+            Constraint::Exclusive(_) => count <= 1,
+            Constraint::OneOf(_) => count == 1,
+            Constraint::Requires(_, _) => !set[0] || set[1],
+        }
+    }
+
+    fn message(&self, fn_name: &Ident) -> String {
+        let list = |fields: &Vec<Ident>| {
+            fields.iter().map(|v| format!("`{}`", v)).collect::<Vec<_>>().join(", ")
+        };
+        match self {
+            Constraint::Exclusive(fields) => format!(
+                "only one of {} can be set when calling `{}`",
+                list(fields),
+                fn_name
+            ),
+            Constraint::OneOf(fields) => format!(
+                "exactly one of {} must be set when calling `{}`",
+                list(fields),
+                fn_name
+            ),
+            Constraint::Requires(first, second) => format!(
+                "`{}` requires `{}` to be set when calling `{}`",
+                first, second, fn_name
+            ),
+        }
+    }
+
+    /// Whether the builder can check the constraint from which setters were called. Flags can be set to `false`,
+    /// so constraints on them are only checked when the fn is called, going by their values
+    pub fn is_typed(&self, structure: &Structure) -> bool {
+        !self.fields().iter().any(|name| is_flag(structure, name))
+    }
+
+    /// The constraint as it was written, for error messages
+    pub fn describe(&self) -> String {
+        match self {
+            Constraint::Requires(first, second) => format!("requires({} = \"{}\")", first, second),
+            _ => {
+                let fields: Vec<String> = self.fields().iter().map(|v| format!("{}", v)).collect();
+                format!("{}({})", self.kind(), fields.join(", "))
+            }
+        }
+    }
+
+    fn trait_name(&self, fn_name: &Ident) -> Ident {
+        let fields: Vec<String> = self.fields().iter().map(|v| format!("{}", v)).collect();
+        Ident::new(
+            &format!("{}_{}_{}", fn_name, self.kind(), fields.join("_")),
+            Span::call_site(),
+        )
+    }
+}
+
+fn field_names(list: &syn::MetaList) -> Vec<Ident> {
+    list.nested
+        .iter()
+        .map(|value| match value {
+            NestedMeta::Meta(Meta::Word(word)) => word.clone(),
+            _ => panic!(
+                "Wrong format for {}. Expected a list of parameter names, got '{}'",
+                &list.ident,
+                quote!(#value)
+            ),
+        }).collect()
+}
+
+/// Parses `exclusive(a, b)`, `one_of(a, b)` or `requires(a = "b", ...)`
+pub fn parse_constraints(meta: &Meta) -> Vec<Constraint> {
+    let list = match meta {
+        Meta::List(list) => list,
+        _ => panic!("Expected a list of parameter names, got '{}'", quote!(#meta)),
+    };
+    match format!("{}", &list.ident).as_str() {
+        "exclusive" => vec![Constraint::Exclusive(field_names(list))],
+        "one_of" => vec![Constraint::OneOf(field_names(list))],
+        "requires" => list
+            .nested
+            .iter()
+            .map(|value| match value {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    ident,
+                    lit: Lit::Str(lit_str),
+                    ..
+                })) => Constraint::Requires(
+                    ident.clone(),
+                    lit_str.parse().unwrap_or_else(|_| {
+                        panic!("Expected a parameter name, got '{}'", lit_str.value())
+                    }),
+                ),
+                _ => panic!(
+                    "Wrong format for requires. Expected name = \"other_name\", got '{}'",
+                    quote!(#value)
+                ),
+            }).collect(),
+        other => panic!("Unknown constraint '{}'", other),
+    }
+}
+
+/// Checks that every constrained parameter exists and has a default, so it can be left unset
+pub fn validate(structure: &Structure) {
+    for constraint in &structure.constraints {
+        if constraint.fields().len() < 2 {
+            panic!("{} needs at least two parameters", constraint.kind());
+        }
+        for name in constraint.fields() {
+            match structure.fields.iter().find(|f| &f.name == name) {
+                None => panic!(
+                    "Cannot use '{}' in {}(..) - there's no parameter with that name",
+                    name,
+                    constraint.kind()
+                ),
+                Some(field) if !field.has_default() => panic!(
+                    "Cannot use '{}' in {}(..) - it needs a default value, so it can be left unset",
+                    name,
+                    constraint.kind()
+                ),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Whether the parameter is a `bool`, which only counts as set for constraints when it's `true`
+pub fn is_flag(structure: &Structure, name: &Ident) -> bool {
+    let field = structure.fields.iter().find(|f| &f.name == name).unwrap();
+    let bool_ty: syn::Type = syn::parse_quote!(bool);
+    field.declared_ty == bool_ty && !field.is_try_into()
+}
+
+/// The type of the defaulted field, without the option wrapping
+fn inner_type(structure: &Structure, name: &Ident) -> syn::Type {
+    let field = structure.fields.iter().find(|f| &f.name == name).unwrap();
+    match get_option_type(field.ty.clone()) {
+        IsOption::True(ty) => ty,
+        IsOption::False => field.ty.clone(),
+    }
+}
+
+/// A marker trait per constraint, implemented for every allowed combination of set (`Option<T>`)
+/// and unset (`Unset`) builder states - so invalid combinations fail to build with a clear message
+pub fn gen_traits(structure: &Structure) -> TokenStream {
+    let vis = &structure.vis;
+    let traits = structure.constraints.iter().filter(|c| c.is_typed(structure)).map(|constraint| {
+        let trait_name = constraint.trait_name(&structure.names.base);
+        let message = constraint.message(&structure.ident);
+        let fields = constraint.fields();
+        let count = fields.len();
+
+        let impls = (0..(1u32 << count)).filter_map(|mask| {
+            let set: Vec<bool> = (0..count).map(|i| mask & (1 << i) != 0).collect();
+            if !constraint.allows(&set) {
+                return None;
+            }
+            let states = fields.iter().zip(set.iter()).map(|(name, is_set)| {
+                if *is_set {
+                    let ty = inner_type(structure, name);
+                    quote!(Option<#ty>)
+                } else {
+                    quote!(Unset)
+                }
+            });
+            Some(quote!(impl #trait_name for (#(#states,)*) {}))
+        });
+
+        quote!(
+            #[doc(hidden)]
+            #[allow(non_camel_case_types)]
+            #[diagnostic::on_unimplemented(message = #message, label = #message)]
+            #vis trait #trait_name {}
+            #(#impls)*
+        )
+    });
+    quote!(#(#traits)*)
+}
+
+/// The where clauses to put on the builder's `build` and `deconstruct` impls
+pub fn where_clauses(structure: &Structure) -> Vec<TokenStream> {
+    let tracked = structure
+        .fields
+        .iter()
+        .filter(|f| structure.is_tracked(f))
+        .map(|f| {
+            let name = &f.name;
+            let ty = inner_type(structure, name);
+            quote!(#name: crate::MaybeSet<#ty>)
+        });
+    let constraints = structure.constraints.iter().filter(|c| c.is_typed(structure)).map(|constraint| {
+        let trait_name = constraint.trait_name(&structure.names.base);
+        let fields = constraint.fields();
+        quote!((#(#fields,)*): #trait_name)
+    });
+    tracked.chain(constraints).collect()
+}
+
+/// The parameter each constraint is reported for, its message, and whether it's met - going by `is_set`,
+/// which says whether a parameter counts as set
+fn checks<'a>(
+    structure: &'a Structure,
+    constraints: impl Iterator<Item = &'a Constraint> + 'a,
+    is_set: impl Fn(&Ident) -> TokenStream + 'a,
+) -> impl Iterator<Item = (String, String, TokenStream)> + 'a {
+    constraints.map(move |constraint| {
+        let param = format!("{}", constraint.fields()[0]);
+        let message = constraint.message(&structure.ident);
+        let set: Vec<TokenStream> = constraint.fields().into_iter().map(&is_set).collect();
+        let check = match constraint {
+            Constraint::Exclusive(_) => quote!([#(#set),*].iter().filter(|v| **v).count() <= 1),
+            Constraint::OneOf(_) => quote!([#(#set),*].iter().filter(|v| **v).count() == 1),
+            Constraint::Requires(_, _) => {
+                let (first, second) = (&set[0], &set[1]);
+                quote!(!#first || #second)
+            }
+        };
+        (param, message, check)
+    })
+}
+
+/// The variable holding whether a parameter of a constraint on flags was set, from before its default is made
+fn set_var(name: &Ident) -> Ident {
+    Ident::new(&format!("__set_{}", name), Span::call_site())
+}
+
+/// Runtime checks for the fn body, for when it's called directly instead of through the builder, and for
+/// constraints on flags. A constraint that isn't met returns early with an `ArgError` for its first parameter.
+///
+/// The first part goes before the defaults are made, and the second after - constraints on flags go by their
+/// values, so a flag left to a default of `true` counts as set. Whether their other parameters were set is
+/// noted down in the first part, as it can't be told once their defaults are made
+pub fn runtime_checks(structure: &Structure, map_err: Option<&TokenStream>) -> (TokenStream, TokenStream) {
+    let early_return = |(param, message, check): (String, String, TokenStream)| {
+        let map_err = map_err.expect("constraints need an error to return");
+        quote!(
+            if !(#check) {
+                return ::std::result::Result::Err((#map_err)(crate::ArgError::new(#param, #message)));
+            }
+        )
+    };
+    let typed = checks(
+        structure,
+        structure.constraints.iter().filter(|c| c.is_typed(structure)),
+        |name| quote!(#name.is_some()),
+    ).map(early_return);
+
+    let on_flags = || structure.constraints.iter().filter(|c| !c.is_typed(structure));
+    let mut noted: Vec<&Ident> = on_flags()
+        .flat_map(|c| c.fields())
+        .filter(|name| !is_flag(structure, name))
+        .collect();
+    noted.sort();
+    noted.dedup();
+    let vars: Vec<Ident> = noted.iter().map(|name| set_var(name)).collect();
+    let flag_checks = checks(structure, on_flags(), |name| {
+        if is_flag(structure, name) {
+            quote!(#name)
+        } else {
+            let var = set_var(name);
+            quote!(#var)
+        }
+    }).map(early_return);
+
+    (
+        quote!(
+            #(#typed)*
+            #(let #vars = #noted.is_some();)*
+        ),
+        quote!(#(#flag_checks)*),
+    )
+}
+
+/// The checks for the runtime builder's `try_build`, on the builder's fields. Constraints on flags whose
+/// defaults are made when the fn is called are left to the fn, as only it knows their values
+pub fn builder_checks(structure: &Structure) -> TokenStream {
+    let known = || {
+        structure.constraints.iter().filter(|c| {
+            c.fields().iter().all(|name| {
+                let field = structure.fields.iter().find(|f| &f.name == *name).unwrap();
+                !is_flag(structure, name) || !crate::defaults::is_deferred(structure, field)
+            })
+        })
+    };
+    let mut names1: Vec<&Ident> = known().flat_map(|c| c.fields()).collect();
+    names1.sort();
+    names1.dedup();
+    let names2 = names1.clone();
+    let is_set = |name: &Ident| {
+        if is_flag(structure, name) {
+            quote!(matches!(#name, Some(true)))
+        } else {
+            quote!(#name.is_some())
+        }
+    };
+    let checks = checks(structure, known(), is_set).map(|(param, message, check)| {
+        quote!(
+            if !(#check) {
+                return ::std::result::Result::Err(crate::BuildError::Invalid(crate::ArgError::new(#param, #message)));
+            }
+        )
+    });
+    quote!(
+        #(let #names1 = &self.#names2;)*
+        #(#checks)*
+    )
+}
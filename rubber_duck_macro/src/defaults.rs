@@ -0,0 +1,362 @@
+use crate::parse_fn::{Field, FieldRole, Structure};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream, Result};
+use syn::{parenthesized, parse_quote, Expr, Ident, LitStr, NestedMeta, Token, Type};
+
+/// How the default value of a parameter is made, when its argument is left out
+#[derive(Clone, Debug)]
+pub enum DefaultValue {
+    /// An expression, from `name = "expression"` or `name = default`
+    Expr(Expr),
+    /// An environment variable, read when the fn is called - from `name = env("VAR", fallback)`
+    Env { var: LitStr, fallback: Option<Box<Expr>> },
+    /// Expressions picked by `#[cfg(..)]`, from `defaults_cfg(predicate, name = "expression")` - the first
+    /// predicate that holds wins, falling back to the expression in `defaults(..)` if none do
+    Cfg { variants: Vec<(NestedMeta, Expr)>, otherwise: Box<Expr> },
+    /// An expression that's only evaluated the first time it's needed, and cached for every call after that -
+    /// from `name = once "expression"`
+    Once(Box<Expr>),
+    /// An expression that can use `?`, which returns early from the fn - from `name = try "expression"`
+    Try(Box<Expr>),
+}
+
+impl Parse for DefaultValue {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(LitStr) {
+            return Ok(DefaultValue::Expr(input.parse::<LitStr>()?.parse()?));
+        }
+        // `try` is a keyword
+        let word = input.call(Ident::parse_any)?;
+        match format!("{}", word).as_str() {
+            // Only used when asked for, as `Default` says nothing about whether the value is cheap to make
+            "default" => Ok(DefaultValue::Expr(parse_quote!(::std::default::Default::default()))),
+            "env" => {
+                let content;
+                parenthesized!(content in input);
+                let var = content.parse()?;
+                let fallback = if content.peek(Token![,]) {
+                    content.parse::<Token![,]>()?;
+                    Some(Box::new(content.parse()?))
+                } else {
+                    None
+                };
+                Ok(DefaultValue::Env { var, fallback })
+            }
+            "once" => Ok(DefaultValue::Once(Box::new(input.parse::<LitStr>()?.parse()?))),
+            "try" => Ok(DefaultValue::Try(Box::new(input.parse::<LitStr>()?.parse()?))),
+            other => Err(syn::parse::Error::new(
+                word.span(),
+                format!(
+                    "expected a quoted expression, `default`, `env(..)`, `once \"..\"` or `try \"..\"`, got '{}'",
+                    other
+                ),
+            )),
+        }
+    }
+}
+
+impl DefaultValue {
+    /// The default as an expression, if it doesn't depend on anything outside the program, and can be evaluated
+    /// wherever it's needed
+    pub fn fixed_expr(&self) -> Option<TokenStream> {
+        match self {
+            DefaultValue::Expr(expr) => Some(quote!(#expr)),
+            DefaultValue::Env { .. } | DefaultValue::Once(_) | DefaultValue::Try(_) => None,
+            DefaultValue::Cfg { variants, otherwise } => {
+                // Attributes can't go on expressions, but they can go on match arms - so exactly one arm is left
+                let mut previous: Vec<&NestedMeta> = vec![];
+                let mut arms = vec![];
+                for (predicate, expr) in variants {
+                    let earlier = &previous;
+                    arms.push(quote!(#[cfg(all(#(not(#earlier),)* #predicate))] () => #expr,));
+                    previous.push(predicate);
+                }
+                arms.push(quote!(#[cfg(not(any(#(#previous),*)))] () => #otherwise,));
+                Some(quote!(match () { #(#arms)* }))
+            }
+        }
+    }
+
+    /// Describes the default's value, for the docs
+    fn describe_fixed(&self) -> Option<String> {
+        match self {
+            DefaultValue::Expr(expr) => Some(format!("`{}`", crate::doc::pretty(expr))),
+            DefaultValue::Env { fallback, .. } => {
+                fallback.as_ref().map(|v| format!("`{}`", crate::doc::pretty(v)))
+            }
+            DefaultValue::Cfg { variants, otherwise } => {
+                let mut described: Vec<String> = variants
+                    .iter()
+                    .map(|(predicate, expr)| {
+                        format!("`{}` with `cfg({})`", crate::doc::pretty(expr), crate::doc::pretty(predicate))
+                    }).collect();
+                described.push(format!("otherwise `{}`", crate::doc::pretty(otherwise)));
+                Some(described.join(", "))
+            }
+            DefaultValue::Once(expr) => Some(format!("`{}` (evaluated once)", crate::doc::pretty(expr))),
+            DefaultValue::Try(expr) => Some(format!("`{}` (returning early if it fails)", crate::doc::pretty(expr))),
+        }
+    }
+
+    /// Adds an expression to use when `predicate` holds, from `defaults_cfg(..)`
+    pub fn add_cfg(&mut self, name: &str, predicate: NestedMeta, expr: Expr) {
+        match self {
+            DefaultValue::Expr(otherwise) => {
+                *self = DefaultValue::Cfg {
+                    variants: vec![(predicate, expr)],
+                    otherwise: Box::new(otherwise.clone()),
+                }
+            }
+            DefaultValue::Cfg { variants, .. } => variants.push((predicate, expr)),
+            DefaultValue::Env { .. } | DefaultValue::Once(_) | DefaultValue::Try(_) => panic!(
+                "Cannot use defaults_cfg for '{}' - its default in defaults(..) needs to be a plain expression",
+                name
+            ),
+        }
+    }
+}
+
+/// Whether the default is looked up when the fn is called, which can fail
+pub fn is_runtime(structure: &Structure, field: &Field<FieldRole>) -> bool {
+    match field.default_value() {
+        Some(DefaultValue::Env { .. }) => true,
+        Some(_) => structure.defaults_file.is_some() && !field.is_optional(),
+        None => false,
+    }
+}
+
+/// Whether the default can only be made when the fn is called, e.g. because it's looked up or cached
+pub fn is_made_when_called(structure: &Structure, field: &Field<FieldRole>) -> bool {
+    match field.default_value() {
+        Some(DefaultValue::Once(_)) | Some(DefaultValue::Try(_)) => true,
+        Some(_) => is_runtime(structure, field) || field.is_overridable(),
+        None => false,
+    }
+}
+
+/// Whether the default is made in the fn rather than the builders - so it's only made when it's needed. A fn
+/// taking a struct of named parameters makes all of them, as `None` in the struct is the same as unset, and so
+/// they can use the parameters before them. So does a method, so they can read `self`
+pub fn is_deferred(structure: &Structure, field: &Field<FieldRole>) -> bool {
+    is_made_when_called(structure, field)
+        || ((structure.named_param.is_some() || structure.method.is_some()) && field.has_default())
+}
+
+/// Whether the default can return early from the fn with `?`
+pub fn is_try(field: &Field<FieldRole>) -> bool {
+    matches!(field.default_value(), Some(DefaultValue::Try(_)))
+}
+
+/// Describes where the default comes from, in order, for the docs
+pub fn describe(structure: &Structure, field: &Field<FieldRole>) -> String {
+    let mut sources = vec![];
+    if field.is_overridable() {
+        sources.push("an override from `with_defaults`".to_owned());
+    }
+    if let Some(DefaultValue::Env { var, .. }) = field.default_value() {
+        sources.push(format!("`${}`", var.value()));
+    }
+    if let (Some(path), false) = (&structure.defaults_file, field.is_optional()) {
+        sources.push(format!("`{}` in `{}`", &field.name, path.value()));
+    }
+    if let Some(fixed) = field.default_value().and_then(|v| v.describe_fixed()) {
+        sources.push(fixed);
+    }
+    sources.join(", or ")
+}
+
+/// The value of the field when its argument was left out, for the rewritten fn body. Defaults that are looked
+/// up when the fn is called return early with an `ArgError` (through `map_err`) if they can't be parsed
+pub fn gen_default(structure: &Structure, field: &Field<FieldRole>, map_err: Option<&TokenStream>) -> TokenStream {
+    let param = format!("{}", &field.name);
+    let declared = &field.declared_ty;
+    let fail = |err: TokenStream| {
+        let map_err = map_err.expect("defaults looked up when called need an error to return");
+        quote!(return ::std::result::Result::Err((#map_err)(#err)))
+    };
+
+    let mut lookups = vec![];
+    let fallback = match field.default_value() {
+        Some(DefaultValue::Env { var, fallback }) => {
+            lookups.push(quote!(crate::default_from_env::<#declared>(#param, #var)));
+            match fallback {
+                Some(fallback) => quote!(#fallback),
+                None => {
+                    let mut sources = format!("the environment variable `{}`", var.value());
+                    if let Some(path) = &structure.defaults_file {
+                        sources.push_str(&format!(" or the config file `{}`", path.value()));
+                    }
+                    fail(quote!(crate::missing_default(#param, #sources)))
+                }
+            }
+        }
+        Some(DefaultValue::Once(expr)) => gen_once(&param, declared, expr),
+        // Evaluated straight in the fn body, so `?` goes through the fn's own return type
+        Some(DefaultValue::Try(expr)) => quote!(#expr),
+        Some(fixed) => fixed.fixed_expr().unwrap(),
+        None => panic!("'{}' has no default", param),
+    };
+    if let (Some(path), false) = (&structure.defaults_file, field.is_optional()) {
+        let section = format!("{}", &structure.ident);
+        lookups.push(quote!(crate::default_from_file::<#declared>(#param, #path, #section, #param)));
+    }
+
+    let value = lookups.into_iter().rev().fold(fallback, |otherwise, lookup| {
+        let fail = fail(quote!(__err));
+        quote!(
+            match #lookup {
+                ::std::result::Result::Ok(Some(__value)) => __value,
+                ::std::result::Result::Ok(None) => #otherwise,
+                ::std::result::Result::Err(__err) => #fail,
+            }
+        )
+    });
+
+    // Overrides come first, as they're the most specific
+    let value = if field.is_overridable() {
+        let overrides = &structure.names.overrides;
+        let current = current_override(field);
+        quote!(
+            match #overrides::#current() {
+                Some(__value) => __value,
+                None => #value,
+            }
+        )
+    } else {
+        value
+    };
+
+    if field.is_try_into() {
+        quote!(::std::result::Result::Ok(#value))
+    } else {
+        value
+    }
+}
+
+/// Caches the value in a static, which is local to the fn body. A `&'static T` parameter gets a reference to a
+/// cached `T` (boxed, if it's a `str` or a slice), and any other type gets a clone - the bounds on the helpers reject
+/// types that can't be shared
+fn gen_once(param: &str, declared: &Type, expr: &Expr) -> TokenStream {
+    match declared {
+        Type::Reference(reference) => {
+            let is_static = reference.lifetime.as_ref().is_some_and(|l| l.ident == "static");
+            if !is_static || reference.mutability.is_some() {
+                panic!(
+                    "Cannot use a once default for '{}' - it needs to be an owned type, or a `&'static` reference",
+                    param
+                );
+            }
+            let cached = &reference.elem;
+            let is_unsized = match **cached {
+                Type::Slice(_) => true,
+                Type::Path(ref path) => path.qself.is_none() && path.path.is_ident("str"),
+                Type::TraitObject(_) => panic!(
+                    "Cannot use a once default for '{}' - a trait object can't be made from the expression, so \
+                     cache a sized type and take a `&'static` reference to that",
+                    param
+                ),
+                _ => false,
+            };
+            if is_unsized {
+                return quote!({
+                    static __CACHE: ::std::sync::OnceLock<::std::boxed::Box<#cached>> = ::std::sync::OnceLock::new();
+                    crate::cached_default_boxed(&__CACHE, || #expr)
+                });
+            }
+            quote!({
+                static __CACHE: ::std::sync::OnceLock<#cached> = ::std::sync::OnceLock::new();
+                crate::cached_default_ref(&__CACHE, || #expr)
+            })
+        }
+        _ => quote!({
+            static __CACHE: ::std::sync::OnceLock<#declared> = ::std::sync::OnceLock::new();
+            crate::cached_default(&__CACHE, || #expr)
+        }),
+    }
+}
+
+/// Named so they can't clash with the setters, which are named after the parameters
+fn current_override(field: &Field<FieldRole>) -> Ident {
+    Ident::new(&format!("__current_{}", &field.name), Span::call_site())
+}
+
+/// The overrides for the `overridable(..)` defaults, and the thread local stack they're kept in
+pub fn gen_overrides(structure: &Structure) -> TokenStream {
+    let fields: Vec<_> = structure.fields.iter().filter(|f| f.is_overridable()).collect();
+    if fields.is_empty() {
+        return quote!();
+    }
+    let struct_name = &structure.names.args;
+    let unset_generics = structure.generic_args(true);
+    let overrides = &structure.names.overrides;
+    let vis = &structure.vis;
+    let names = fields.iter().map(|f| &f.name);
+    let types = fields.iter().map(|f| &f.declared_ty);
+    let setters = fields.iter().map(|f| {
+        let name = &f.name;
+        let ty = &f.declared_ty;
+        let doc = format!(" Overrides the default of `{}`, which is {}", name, describe(structure, f));
+        quote!(
+            #[doc = #doc]
+            #vis fn #name(mut self, value: #ty) -> Self {
+                self.#name = Some(value);
+                self
+            }
+        )
+    });
+    let currents = fields.iter().map(|f| {
+        let name = &f.name;
+        let ty = &f.declared_ty;
+        let current = current_override(f);
+        quote!(
+            fn #current() -> Option<#ty> {
+                Self::__stack().with(|stack| stack.borrow().iter().rev().find_map(|v| v.#name.clone()))
+            }
+        )
+    });
+    let doc = format!(
+        "Overrides for the defaults of {}, for every call in a scope - see `rubber_duck::with_defaults`",
+        crate::doc::fn_link(structure)
+    );
+
+    quote!(
+        #[doc = #doc]
+        #[allow(non_camel_case_types)]
+        #[derive(Default)]
+        #vis struct #overrides {
+            #(#names: Option<#types>,)*
+        }
+
+        #[allow(non_camel_case_types)]
+        impl #overrides {
+            #(#setters)*
+
+            fn __stack() -> &'static ::std::thread::LocalKey<::std::cell::RefCell<Vec<#overrides>>> {
+                thread_local!(static STACK: ::std::cell::RefCell<Vec<#overrides>> = ::std::cell::RefCell::new(Vec::new()));
+                &STACK
+            }
+
+            #(#currents)*
+        }
+
+        #[allow(non_camel_case_types)]
+        impl crate::DefaultOverrides for #overrides {
+            fn push(self) {
+                Self::__stack().with(|stack| stack.borrow_mut().push(self));
+            }
+
+            fn pop() {
+                Self::__stack().with(|stack| stack.borrow_mut().pop());
+            }
+        }
+
+        impl #struct_name#unset_generics {
+            #[allow(non_camel_case_types)]
+            #vis fn overrides() -> #overrides {
+                #overrides::default()
+            }
+        }
+    )
+}
@@ -0,0 +1,390 @@
+use crate::parse_fn::{Field, FieldRole, Structure};
+use proc_macro2::{Delimiter, Group, Spacing, TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+use std::collections::HashMap;
+
+/// Rust doesn't allow doc comments on fn parameters, so this strips them out of the fn's
+/// parameter list before it's parsed, returning them by parameter name
+pub fn strip_param_docs(input: TokenStream) -> (TokenStream, HashMap<String, Vec<String>>) {
+    let (tokens, docs) = strip_fn_param_docs(input, false);
+    (tokens, docs.into_iter().next().map(|(_, docs)| docs).unwrap_or_default())
+}
+
+/// The same for every fn declared in the tokens, e.g. the methods in the body of an impl block - by fn name
+pub fn strip_method_param_docs(input: TokenStream) -> (TokenStream, HashMap<String, HashMap<String, Vec<String>>>) {
+    strip_fn_param_docs(input, true)
+}
+
+fn strip_fn_param_docs(input: TokenStream, every_fn: bool) -> (TokenStream, HashMap<String, HashMap<String, Vec<String>>>) {
+    let mut docs: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+    let mut seen_fn = false;
+    let mut fn_name = String::new();
+    let mut angle_depth = 0;
+    let mut done = false;
+    let mut prev_joint_dash = false;
+
+    let tokens = input
+        .into_iter()
+        .map(|token| {
+            let mut token = token;
+            if done {
+                return token;
+            }
+            match token {
+                TokenTree::Ident(ref ident) if !seen_fn && format!("{}", ident) == "fn" => {
+                    seen_fn = true;
+                    fn_name.clear();
+                }
+                TokenTree::Ident(ref ident) if seen_fn && fn_name.is_empty() => {
+                    fn_name = format!("{}", ident);
+                }
+                TokenTree::Punct(ref punct) if seen_fn => {
+                    match punct.as_char() {
+                        '<' => angle_depth += 1,
+                        '>' if !prev_joint_dash => angle_depth -= 1,
+                        _ => {}
+                    }
+                    prev_joint_dash = punct.as_char() == '-' && punct.spacing() == Spacing::Joint;
+                    return token;
+                }
+                TokenTree::Group(ref group)
+                    if seen_fn && angle_depth == 0 && group.delimiter() == Delimiter::Parenthesis =>
+                {
+                    // The next fn's parameters are after this one's body, which is a group of its own
+                    done = !every_fn;
+                    seen_fn = false;
+                    let mut stripped = Group::new(
+                        Delimiter::Parenthesis,
+                        strip_params(group.stream(), docs.entry(fn_name.clone()).or_default()),
+                    );
+                    stripped.set_span(group.span());
+                    token = TokenTree::Group(stripped);
+                }
+                _ => {}
+            }
+            prev_joint_dash = false;
+            token
+        }).collect();
+
+    (tokens, docs)
+}
+
+fn strip_params(params: TokenStream, docs: &mut HashMap<String, Vec<String>>) -> TokenStream {
+    let mut out: Vec<TokenTree> = vec![];
+    let mut pending_docs = vec![];
+    let mut at_param_start = true;
+    let mut angle_depth = 0;
+    let mut prev_joint_dash = false;
+    let mut iter = params.into_iter().peekable();
+
+    while let Some(token) = iter.next() {
+        if at_param_start {
+            if let TokenTree::Punct(ref punct) = token {
+                if punct.as_char() == '#' {
+                    if let Some(TokenTree::Group(group)) = iter.peek() {
+                        if let Some(doc) = doc_value(group) {
+                            pending_docs.push(doc);
+                            iter.next();
+                            continue;
+                        }
+                    }
+                }
+            }
+            if let TokenTree::Ident(ref ident) = token {
+                let name = format!("{}", ident);
+                if name != "mut" {
+                    at_param_start = false;
+                    if !pending_docs.is_empty() {
+                        docs.insert(name, pending_docs.split_off(0));
+                    }
+                }
+            }
+        }
+        if let TokenTree::Punct(ref punct) = token {
+            match punct.as_char() {
+                '<' => angle_depth += 1,
+                '>' if !prev_joint_dash => angle_depth -= 1,
+                ',' if angle_depth == 0 => at_param_start = true,
+                _ => {}
+            }
+            prev_joint_dash = punct.as_char() == '-' && punct.spacing() == Spacing::Joint;
+        } else {
+            prev_joint_dash = false;
+        }
+        out.push(token);
+    }
+
+    out.into_iter().collect()
+}
+
+/// Gets the text out of the inside of a `#[doc = "..."]` attribute
+fn doc_value(group: &Group) -> Option<String> {
+    if group.delimiter() != Delimiter::Bracket {
+        return None;
+    }
+    let inner = group.stream();
+    let attr: syn::Attribute = syn::parse_quote!(#[#inner]);
+    match attr.interpret_meta() {
+        Some(syn::Meta::NameValue(syn::MetaNameValue {
+            ref ident,
+            lit: syn::Lit::Str(ref lit),
+            ..
+        })) if format!("{}", ident) == "doc" => Some(lit.value()),
+        _ => None,
+    }
+}
+
+/// Renders tokens as code for the docs, without most of the spacing `quote` adds
+pub fn pretty(tokens: &impl ToTokens) -> String {
+    let raw = format!("{}", quote!(#tokens));
+    let mut pretty = raw;
+    for (from, to) in &[
+        (" :: ", "::"),
+        (":: ", "::"),
+        (" < ", "<"),
+        ("< ", "<"),
+        (" >", ">"),
+        (" ,", ","),
+        ("& ", "&"),
+        (" . ", "."),
+        (" (", "("),
+        ("( ", "("),
+        (" )", ")"),
+        (" [", "["),
+        ("[ ", "["),
+        (" ]", "]"),
+        ("! ", "!"),
+    ] {
+        pretty = pretty.replace(from, to);
+    }
+    pretty
+}
+
+fn describe_field(structure: &Structure, field: &Field<FieldRole>) -> String {
+    let kind = match field.default_value() {
+        _ if field.is_positional() => "positional".to_owned(),
+        Some(_) => format!("named, defaults to {}", crate::defaults::describe(structure, field)),
+        None => "named, required".to_owned(),
+    };
+    let mut line = format!("* `{}`: `{}` ({})", &field.name, pretty(&field.declared_ty), kind);
+    if field.nullable {
+        line.push_str(" - nullable, so `None` is a value rather than a request for the default");
+    }
+    if field.is_try_into() {
+        line.push_str(&format!(" - takes anything that can be converted with `TryInto<{}>`", pretty(&field.declared_ty)));
+    }
+    if let Some((_, check)) = structure.validations.iter().find(|(name, _)| name == &field.name) {
+        line.push_str(&format!(" - must satisfy `{}`", pretty(check)));
+    }
+    if !field.docs.is_empty() {
+        line.push_str(" - ");
+        line.push_str(&field.docs.iter().map(|v| v.trim()).collect::<Vec<_>>().join(" "));
+    }
+    line
+}
+
+/// The docs for a parameter's builder setter (and field), so IDEs show what the option does.
+/// Positional setters are `next()`, unless `by_name` is set
+pub fn setter_docs(structure: &Structure, field: &Field<FieldRole>, by_name: bool) -> Vec<String> {
+    let mut docs = field.docs.clone();
+    if field.is_positional() && !by_name {
+        if !docs.is_empty() {
+            docs.push("".to_owned());
+        }
+        docs.push(format!(" Sets `{}`, the next positional argument", &field.name));
+    } else if docs.is_empty() {
+        docs.push(format!(" Sets the `{}` argument", &field.name));
+    }
+    if field.has_default() {
+        docs.push("".to_owned());
+        docs.push(format!(" Defaults to {}", crate::defaults::describe(structure, field)));
+    }
+    docs
+}
+
+/// The docs for the `maybe_` setter of a defaulted parameter
+pub fn maybe_setter_docs(field: &Field<FieldRole>) -> Vec<String> {
+    let mut docs = field.docs.clone();
+    if !docs.is_empty() {
+        docs.push("".to_owned());
+    }
+    docs.push(format!(" Sets the `{}` argument if `value` is `Some`, otherwise uses its default", &field.name));
+    docs
+}
+
+/// A link to the fn (or method) in the docs of the items generated for it
+pub fn fn_link(structure: &Structure) -> String {
+    match &structure.method {
+        Some(method) => format!("[{0}::{1}]({0}::{1})", &method.type_name, &structure.ident),
+        None => format!("[{0}](fn.{0}.html)", &structure.ident),
+    }
+}
+
+/// Generates an example call, using the parameter names for the values
+fn example_calls(structure: &Structure, with_defaults: bool) -> Vec<String> {
+    let name = &structure.ident;
+    let macro_name = &structure.names.macro_name;
+    let positional: Vec<String> = structure.positional().map(|f| format!("{}", f.name)).collect();
+    let named: Vec<String> = structure
+        .named()
+        .iter()
+        .filter(|f| with_defaults || f.extra.default.is_none())
+        .map(|f| format!("{}", f.name))
+        .collect();
+
+    let mut n_args = positional.clone();
+    if !named.is_empty() {
+        n_args.push(format!("{{{},}}", named.join(", ")));
+    }
+    let callee = match &structure.method {
+        Some(method) => format!("{}.{}", crate::method::receiver_example(method), name),
+        None => format!("{}", name),
+    };
+    let mut calls = vec![format!("n!({}{{{}}});", callee, n_args.join(", "))];
+
+    if cfg!(feature = "nightly") && structure.method.is_none() {
+        // Every positional argument is followed by a comma in the macro, even the last one
+        let mut macro_args: Vec<String> = positional.into_iter().map(|v| format!("{},", v)).collect();
+        let named_args: Vec<String> = named.iter().map(|v| format!("{} => {}", v, v)).collect();
+        if !named_args.is_empty() {
+            macro_args.push(named_args.join(", "));
+        }
+        calls.push(format!("{}!({});", macro_name, macro_args.join(" ")));
+    }
+    calls
+}
+
+/// The generated rustdoc section describing how to call the fn with named arguments
+pub fn doc_section(structure: &Structure) -> String {
+    let mut lines = vec![
+        "# Arguments".to_owned(),
+        "".to_owned(),
+        "This function can be called with named arguments (and defaults) using `n!`".to_owned(),
+    ];
+    if cfg!(feature = "nightly") && structure.method.is_none() {
+        let last = lines.len() - 1;
+        lines[last].push_str(&format!(" or the `{}!` macro", &structure.names.macro_name));
+    }
+    lines.push("".to_owned());
+    lines.extend(structure.fields.iter().map(|f| describe_field(structure, f)));
+
+    if structure.fields.iter().any(|f| f.has_default()) {
+        lines.push("".to_owned());
+        lines.push(
+            "When calling the function directly, arguments with defaults take an `Option` - pass `None` to use the default."
+                .to_owned(),
+        );
+    }
+
+    if structure.fields.iter().any(|f| crate::defaults::is_runtime(structure, f)) {
+        lines.push("".to_owned());
+        lines.push(
+            "Defaults from the environment or a config file are looked up each time the function is called, and are rejected with an `ArgError` if they can't be parsed."
+                .to_owned(),
+        );
+    }
+
+    if crate::validate::has_checks(structure) {
+        lines.push("".to_owned());
+        lines.push(
+            "Arguments that fail their conversion or check are rejected with an `ArgError`, before the function body is run."
+                .to_owned(),
+        );
+    }
+
+    lines.push("".to_owned());
+    lines.push("# Example Calls".to_owned());
+    lines.push("".to_owned());
+    lines.push("```ignore".to_owned());
+    lines.push("// With only the required arguments".to_owned());
+    lines.extend(example_calls(structure, false));
+    if structure.fields.iter().any(|f| f.has_default()) {
+        lines.push("// Overriding the defaults too".to_owned());
+        lines.extend(example_calls(structure, true));
+    }
+    lines.push("```".to_owned());
+
+    lines.join("\n")
+}
+
+/// The doc section wrapped as an attribute, ready to be added to any of the generated items
+pub fn doc_attr(structure: &Structure) -> TokenStream {
+    let section = doc_section(structure);
+    quote!(#[doc = #section])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_param_docs() {
+        let input: TokenStream = syn::parse_str(
+            r#"
+            /// The fn docs
+            pub fn test<F: Fn(u32) -> u32>(
+                /// The first
+                /// parameter
+                first: HashMap<String, u32>,
+                mut second: F,
+                /// The third parameter
+                third: u32,
+            ) -> u32 {
+                0
+            }
+            "#,
+        ).unwrap();
+        let (stripped, docs) = strip_param_docs(input);
+
+        assert_eq!(
+            Some(&vec![" The first".to_owned(), " parameter".to_owned()]),
+            docs.get("first")
+        );
+        assert_eq!(None, docs.get("second"));
+        assert_eq!(Some(&vec![" The third parameter".to_owned()]), docs.get("third"));
+
+        let parsed: syn::ItemFn = syn::parse2(stripped).unwrap();
+        assert_eq!(3, parsed.decl.inputs.len());
+        assert_eq!(1, parsed.attrs.len());
+    }
+
+    #[test]
+    fn writes_setter_docs() {
+        let mut args = crate::args::parse_args(
+            syn::parse_str(r##"defaults(greeting = r#""Hello".to_owned()"#), positionals(name)"##).unwrap(),
+        );
+        let (input, param_docs) = strip_param_docs(
+            syn::parse_str(
+                r#"
+                fn greet(
+                    /// Who to greet
+                    name: String,
+                    greeting: String,
+                    /// Shown after the greeting
+                    suffix: String,
+                ) -> String {
+                    String::new()
+                }
+                "#,
+            ).unwrap(),
+        );
+        args.docs.extend(param_docs);
+        let mut fn_item: syn::ItemFn = syn::parse2(input).unwrap();
+        let structure = crate::parse_fn::parse_field_decl(&mut args, &mut fn_item);
+        let docs = |name: &str, by_name: bool| {
+            let field = structure.fields.iter().find(|f| f.name == name).unwrap();
+            setter_docs(&structure, field, by_name)
+        };
+
+        assert_eq!(
+            vec![" Who to greet", "", " Sets `name`, the next positional argument"],
+            docs("name", false)
+        );
+        assert_eq!(vec![" Who to greet"], docs("name", true));
+        assert_eq!(
+            vec![" Sets the `greeting` argument", "", " Defaults to `\"Hello\".to_owned()`"],
+            docs("greeting", false)
+        );
+        assert_eq!(vec![" Shown after the greeting"], docs("suffix", false));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::builder::{initial_value, maybe_setter_conversion, maybe_setter_name, setter_conversion};
+use crate::parse_fn::Structure;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::ReturnType;
+
+/// A builder that checks for missing arguments at runtime instead of in its type, for when the
+/// arguments come from config or user input. It has the same setters as the typestate builder,
+/// except positional arguments are set by name rather than with `next()`
+pub fn create_dyn_builder(structure: &Structure, output: &ReturnType) -> TokenStream {
+    let struct_name = &structure.names.args;
+    let builder_name = &structure.names.dyn_builder;
+    let vis = &structure.vis;
+    // Generic over the types of the `impl Trait` parameters, like the args struct - they're inferred from the setters
+    let generics = structure.generic_args(false);
+    let unset_generics = structure.generic_args(true);
+    let type_params = &structure.type_params();
+
+    // Required fields are wrapped in an option to track whether they were set. Defaulted fields
+    // already are one, and start out the same way as in the typestate builder - constrained ones
+    // start unset, as their defaults would count as set
+    let field_decs = structure.fields.iter().map(|f| {
+        let name = &f.name;
+        let ty = &f.ty;
+        if f.has_default() {
+            quote!(#name: #ty,)
+        } else {
+            quote!(#name: Option<#ty>,)
+        }
+    });
+    let initial_values = structure.fields.iter().map(|f| {
+        let name = &f.name;
+        let initial = if structure.is_tracked(f) {
+            quote!(None)
+        } else {
+            initial_value(structure, f).unwrap_or_else(|| quote!(None))
+        };
+        quote!(#name: #initial,)
+    });
+
+    let setters = structure.fields.iter().map(|field| {
+        let name = &field.name;
+        let docs = crate::doc::setter_docs(structure, field, true);
+        let (generics, param_type, convert) = setter_conversion(field);
+        let assign = if field.has_default() {
+            quote!(self.#name = value;)
+        } else {
+            quote!(self.#name = Some(value);)
+        };
+        let setter = quote!(
+            #(#[doc = #docs])*
+            #vis fn #name#generics(mut self, value: #param_type) -> Self {
+                #convert
+                #assign
+                self
+            }
+        );
+        if !field.has_default() {
+            return setter;
+        }
+        let maybe_name = maybe_setter_name(field);
+        let docs = crate::doc::maybe_setter_docs(field);
+        let (generics, param_type, convert) = maybe_setter_conversion(field);
+        quote!(
+            #setter
+
+            #(#[doc = #docs])*
+            #vis fn #maybe_name#generics(mut self, value: #param_type) -> Self {
+                #convert
+                self.#name = value;
+                self
+            }
+        )
+    });
+
+    let required: Vec<_> = structure.fields.iter().filter(|f| !f.has_default()).map(|f| &f.name).collect();
+    let required_names: Vec<_> = required.iter().map(|name| format!("{}", name)).collect();
+    let built_values = structure.fields.iter().map(|f| {
+        let name = &f.name;
+        if f.has_default() {
+            quote!(#name: self.#name,)
+        } else {
+            quote!(#name: self.#name.expect("checked for missing arguments"),)
+        }
+    });
+    let call_args = crate::named_struct::call_args(
+        structure,
+        structure.names().iter().map(|name| quote!(args.#name)).collect(),
+    );
+    let constraint_checks = crate::constraints::builder_checks(structure);
+    let output_ty = crate::builder::output_type(structure, output);
+    let (unsafety, call_doc) = crate::builder::unsafety(structure);
+    let (receiver, call) = crate::method::call(structure, &call_args);
+    let doc = format!(
+        "Builds the arguments for {} at runtime, with the same setters as `{}::builder()`.\n\n\
+         Missing arguments and broken constraints are reported by `try_build` and `try_call`, instead of failing \
+         to compile.",
+        crate::doc::fn_link(structure),
+        struct_name
+    );
+
+    quote!(
+        #[doc = #doc]
+        #[allow(non_camel_case_types)]
+        #vis struct #builder_name#generics {
+            #(#field_decs)*
+        }
+
+        #[allow(non_camel_case_types)]
+        impl <#(#type_params),*> #builder_name#generics {
+            #vis fn new() -> #builder_name#generics {
+                #builder_name {
+                    #(#initial_values)*
+                }
+            }
+
+            #(#setters)*
+
+            /// Builds the arguments, or lists every required argument that wasn't set, or reports the first
+            /// constraint that isn't met
+            #vis fn try_build(self) -> ::std::result::Result<#struct_name#generics, crate::BuildError> {
+                let mut missing: Vec<&'static str> = Vec::new();
+                #(if self.#required.is_none() {
+                    missing.push(#required_names);
+                })*
+                if !missing.is_empty() {
+                    return ::std::result::Result::Err(crate::BuildError::Missing(crate::MissingArgs::new(missing)));
+                }
+                #constraint_checks
+                ::std::result::Result::Ok(#struct_name {
+                    #(#built_values)*
+                })
+            }
+
+            /// Calls the function with the arguments, if they can be built
+            #call_doc
+            #vis #unsafety fn try_call(self #receiver) -> ::std::result::Result<#output_ty, crate::BuildError> {
+                let args = self.try_build()?;
+                ::std::result::Result::Ok(#call)
+            }
+        }
+
+        impl <#(#type_params),*> Default for #builder_name#generics {
+            fn default() -> #builder_name#generics {
+                #builder_name::new()
+            }
+        }
+
+        impl #struct_name#unset_generics {
+            #[allow(non_camel_case_types)]
+            #vis fn dyn_builder<#(#type_params),*>() -> #builder_name#generics {
+                #builder_name::new()
+            }
+        }
+    )
+}
@@ -0,0 +1,72 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{parse_quote, FnArg, ForeignItem, Ident, ItemFn, ItemForeignMod, Pat};
+
+/// Turns an `extern` block declaring one function into a wrapper with the function's name, that the
+/// rest of the macro treats like any other fn - along with the block itself, moved into a private module
+/// so the wrapper can forward to the raw symbol. The wrapper is `unsafe` unless `safe` says otherwise
+pub fn wrap_foreign(foreign: ItemForeignMod, safe: bool) -> (ItemFn, TokenStream) {
+    let mut fns: Vec<_> = foreign
+        .items
+        .iter()
+        .map(|item| match item {
+            ForeignItem::Fn(item) => item.clone(),
+            _ => panic!("Can only be used on extern blocks that declare functions"),
+        }).collect();
+    let mut raw = match (fns.pop(), fns.is_empty()) {
+        (Some(raw), true) => raw,
+        _ => panic!("Can only be used on extern blocks that declare one function, so the options apply to it"),
+    };
+    if raw.decl.variadic.is_some() {
+        panic!("Cannot wrap '{}' - variadic functions aren't supported", &raw.ident);
+    }
+
+    let ident = raw.ident.clone();
+    let module = Ident::new(&format!("__{}_raw", &ident), Span::call_site());
+    let args = raw.decl.inputs.iter().map(|arg| match arg {
+        FnArg::Captured(arg) => match arg.pat {
+            Pat::Ident(ref pat) => pat.ident.clone(),
+            _ => panic!("Cannot wrap '{}' - its parameters need names, to be named arguments", &ident),
+        },
+        _ => panic!("Cannot wrap '{}' - unsupported parameter", &ident),
+    });
+    let call = quote!(#module::#ident(#(#args),*));
+    let block = if safe {
+        parse_quote!({ unsafe { #call } })
+    } else {
+        parse_quote!({ #call })
+    };
+
+    // The symbol's name stays with the declaration, and the docs and anything else go to the wrapper
+    let (raw_attrs, wrapper_attrs) = raw.attrs.drain(..).partition(|attr| attr.path.is_ident("link_name"));
+    raw.attrs = raw_attrs;
+    let wrapper = ItemFn {
+        attrs: wrapper_attrs,
+        vis: raw.vis.clone(),
+        constness: None,
+        unsafety: if safe { None } else { Some(parse_quote!(unsafe)) },
+        asyncness: None,
+        abi: None,
+        ident,
+        decl: raw.decl.clone(),
+        block,
+    };
+
+    raw.vis = parse_quote!(pub(super));
+    let attrs = &foreign.attrs;
+    let abi = &foreign.abi;
+    let declaration = quote!(
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        mod #module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#attrs)*
+            #abi {
+                #raw
+            }
+        }
+    );
+    (wrapper, declaration)
+}
@@ -0,0 +1,264 @@
+use crate::builder::{get_option_type, IsOption};
+use crate::parse_fn::{Field, FieldRole, Structure};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::collections::HashSet;
+use syn::{Ident, LitStr, ReturnType, Type};
+
+/// The options of `ffi`, e.g. `ffi(symbol = "sum", repr_c(Point))`
+#[derive(Clone, Debug, Default)]
+pub struct Ffi {
+    /// The symbol to export the entry point as, instead of `<fn>_ffi`
+    pub symbol: Option<LitStr>,
+    /// The `#[repr(C)]` types that can be used in the entry point, which are written with the same name in C
+    pub repr_c: HashSet<String>,
+}
+
+/// The name of the flag that says whether a defaulted field was set
+fn flag_name(field: &Field<FieldRole>) -> Ident {
+    Ident::new(&format!("has_{}", &field.name), Span::call_site())
+}
+
+/// The type a field has in the `#[repr(C)]` args struct - defaulted fields are set through a flag
+/// instead of an `Option`, so a zeroed (or designated) initializer leaves them to their default.
+/// References stay in an `Option` though, as a zeroed one would be a null reference - null is `None`
+fn ffi_type(field: &Field<FieldRole>) -> Type {
+    if field.is_optional() {
+        match get_option_type(field.declared_ty.clone()) {
+            IsOption::True(Type::Reference(_)) => field.declared_ty.clone(),
+            IsOption::True(inner) => inner,
+            IsOption::False => unreachable!("optional parameters are checked to be options"),
+        }
+    } else if let (true, Type::Reference(_)) = (field.has_default(), &field.declared_ty) {
+        let declared = &field.declared_ty;
+        syn::parse_quote!(Option<#declared>)
+    } else {
+        field.declared_ty.clone()
+    }
+}
+
+/// Whether the field is a reference kept in an `Option` in the args struct, as well as having a flag
+fn keeps_option(field: &Field<FieldRole>) -> bool {
+    match get_option_type(field.declared_ty.clone()) {
+        IsOption::True(Type::Reference(_)) => field.is_optional(),
+        _ => field.has_default() && matches!(field.declared_ty, Type::Reference(_)),
+    }
+}
+
+/// An `extern "C"` entry point for the fn, taking a `#[repr(C)]` args struct with presence flags for the defaulted
+/// fields - along with the C declarations for both, as `C_HEADER` on the struct
+pub fn gen_ffi(structure: &Structure, output: &ReturnType) -> TokenStream {
+    let options = match &structure.ffi {
+        Some(options) => options,
+        None => return quote!(),
+    };
+    check_ffi(structure);
+
+    let fn_name = &structure.ident;
+    let ffi_args = &structure.names.ffi_args;
+    let ffi_fn = &structure.names.ffi_fn;
+    let vis = &structure.vis;
+    let (unsafety, call_doc) = crate::builder::unsafety(structure);
+
+    let field_decs = structure.fields.iter().map(|f| {
+        let name = &f.name;
+        let ty = ffi_type(f);
+        if f.has_default() {
+            let flag = flag_name(f);
+            let doc = format!(" Whether `{}` is set, rather than left to its default", name);
+            quote!(
+                #[doc = #doc]
+                #vis #flag: bool,
+                #vis #name: #ty,
+            )
+        } else {
+            quote!(#vis #name: #ty,)
+        }
+    });
+    let values: Vec<_> = structure.fields.iter().map(|f| {
+        let name = &f.name;
+        if f.has_default() {
+            let flag = flag_name(f);
+            if keeps_option(f) {
+                quote!(if args.#flag { args.#name } else { None })
+            } else {
+                quote!(if args.#flag { Some(args.#name) } else { None })
+            }
+        } else {
+            quote!(args.#name)
+        }
+    }).collect();
+    let call_args = crate::named_struct::call_args(structure, values);
+
+    let struct_doc = format!(
+        "The arguments for [{0}](fn.{0}.html) from C, passed to `{1}`. Defaulted arguments are only used if \
+         their `has_` flag is set.",
+        fn_name, ffi_fn
+    );
+    let fn_doc = format!("Calls [{0}](fn.{0}.html) from C, with the arguments in a struct", fn_name);
+    let header = c_declarations(structure, options, output);
+    let export = match &options.symbol {
+        Some(symbol) => quote!(#[export_name = #symbol]),
+        None => quote!(#[no_mangle]),
+    };
+
+    quote!(
+        #[doc = #struct_doc]
+        #[repr(C)]
+        #[allow(non_camel_case_types)]
+        #vis struct #ffi_args {
+            #(#field_decs)*
+        }
+
+        impl #ffi_args {
+            /// The C declarations of this struct and its entry point, for `rubber_duck::ffi::c_header`
+            #vis const C_HEADER: &'static str = #header;
+        }
+
+        #[doc = #fn_doc]
+        #call_doc
+        #export
+        #vis #unsafety extern "C" fn #ffi_fn(args: #ffi_args) #output {
+            #fn_name(#call_args)
+        }
+    )
+}
+
+/// The parts of the fn that can't be expressed to C
+fn check_ffi(structure: &Structure) {
+    if structure.is_async {
+        panic!("Cannot use ffi on an async fn - its future can't be returned to C");
+    }
+    if let Some(impl_trait) = structure.impl_traits.first() {
+        panic!("Cannot use ffi with '{}' declared as `impl Trait` - C can't be generic over its type", &impl_trait.field);
+    }
+    if crate::validate::has_checks(structure) {
+        panic!(
+            "Cannot use ffi with validate(..), try_into(..) or defaults looked up when called - \
+             their `ArgError` can't be returned to C"
+        );
+    }
+    for field in structure.fields.iter().filter(|f| f.has_default()) {
+        let flag = flag_name(field);
+        if structure.fields.iter().any(|f| f.name == flag) {
+            panic!("Cannot use ffi - the flag for '{}' would clash with the parameter '{}'", &field.name, flag);
+        }
+    }
+}
+
+/// The C declarations of the args struct and the entry point
+fn c_declarations(structure: &Structure, options: &Ffi, output: &ReturnType) -> String {
+    let ffi_args = format!("{}", &structure.names.ffi_args);
+    let mut fields = String::new();
+    for field in &structure.fields {
+        let ty = c_type(&ffi_type(field), &options.repr_c);
+        if field.has_default() {
+            // `*/` can't appear in a comment, but could in a default expression
+            let default = crate::defaults::describe(structure, field).replace("*/", "* /");
+            fields.push_str(&format!("    bool {}; /* defaults to {} */\n", flag_name(field), default));
+        }
+        fields.push_str(&format!("    {} {};\n", ty, &field.name));
+    }
+    let output = match output {
+        ReturnType::Type(_, ty) => c_type(ty, &options.repr_c),
+        ReturnType::Default => "void".to_owned(),
+    };
+    let symbol = match &options.symbol {
+        Some(symbol) => symbol.value(),
+        None => format!("{}", &structure.names.ffi_fn),
+    };
+    format!(
+        "typedef struct {0} {{\n{1}}} {0};\n\n{2} {3}({0} args);\n",
+        ffi_args, fields, output, symbol
+    )
+}
+
+/// Writes a Rust type as a C type, for the header. Apart from primitives and pointers, only the `#[repr(C)]` types
+/// listed in `repr_c(..)` can be used - they're written with the same name in C
+fn c_type(ty: &Type, repr_c: &HashSet<String>) -> String {
+    let unsupported = || -> ! {
+        panic!(
+            "Cannot use ffi - `{}` can't be written as a C type. If it's a #[repr(C)] type declared to C with the same \
+             name, list it in ffi(repr_c(..))",
+            quote!(#ty)
+        )
+    };
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            if let IsOption::True(inner) = get_option_type(ty.clone()) {
+                // Only nullable pointers can be left out of an option in C
+                return match inner {
+                    Type::Reference(_) => c_type(&inner, repr_c),
+                    _ => unsupported(),
+                };
+            }
+            let last = path.path.segments.iter().last().unwrap_or_else(|| unsupported());
+            if !last.arguments.is_empty() {
+                unsupported();
+            }
+            let name = format!("{}", last.ident);
+            let c_name = match name.as_str() {
+                "i8" | "i16" | "i32" | "i64" => format!("int{}_t", &name[1..]),
+                "u8" | "u16" | "u32" | "u64" => format!("uint{}_t", &name[1..]),
+                "isize" => "intptr_t".to_owned(),
+                "usize" => "uintptr_t".to_owned(),
+                "f32" | "c_float" => "float".to_owned(),
+                "f64" | "c_double" => "double".to_owned(),
+                "bool" => "bool".to_owned(),
+                "char" => "uint32_t".to_owned(),
+                "c_void" => "void".to_owned(),
+                "c_char" | "c_short" | "c_int" | "c_long" | "c_longlong" => name[2..].replace("longlong", "long long"),
+                "c_schar" => "signed char".to_owned(),
+                "c_uchar" | "c_ushort" | "c_uint" | "c_ulong" | "c_ulonglong" => {
+                    format!("unsigned {}", name[3..].replace("longlong", "long long"))
+                }
+                _ if repr_c.contains(&name) => name,
+                _ => unsupported(),
+            };
+            c_name
+        }
+        Type::Ptr(ptr) => pointer(c_type(&ptr.elem, repr_c), ptr.const_token.is_some()),
+        Type::Reference(reference) => pointer(c_type(&reference.elem, repr_c), reference.mutability.is_none()),
+        Type::Tuple(tuple) if tuple.elems.is_empty() => "void".to_owned(),
+        Type::Paren(paren) => c_type(&paren.elem, repr_c),
+        _ => unsupported(),
+    }
+}
+
+/// A pointer to `pointee` - `const` goes after the pointee when it's a pointer itself, so it applies to the right one
+fn pointer(pointee: String, is_const: bool) -> String {
+    match (is_const, pointee.ends_with('*')) {
+        (false, _) => format!("{}*", pointee),
+        (true, false) => format!("const {}*", pointee),
+        (true, true) => format!("{} const*", pointee),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(ty: &str) -> String {
+        c_type(&syn::parse_str(ty).unwrap(), &vec!["Point".to_owned()].into_iter().collect())
+    }
+
+    #[test]
+    fn writes_c_types() {
+        assert_eq!("uint32_t", c("u32"));
+        assert_eq!("int64_t", c("i64"));
+        assert_eq!("uintptr_t", c("usize"));
+        assert_eq!("unsigned long long", c("std::os::raw::c_ulonglong"));
+        assert_eq!("char", c("c_char"));
+        assert_eq!("const uint8_t*", c("*const u8"));
+        assert_eq!("const char* const*", c("*const *const c_char"));
+        assert_eq!("double*", c("&mut f64"));
+        assert_eq!("const Point*", c("Option<&Point>"));
+        assert_eq!("void", c("()"));
+    }
+
+    #[test]
+    #[should_panic(expected = "list it in ffi(repr_c(..))")]
+    fn rejects_unlisted_types() {
+        c("Size");
+    }
+}
@@ -0,0 +1,116 @@
+use crate::args::Args;
+use crate::attrs::{AttrLocation, ParsedAttr};
+use crate::defaults::DefaultValue;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::quote;
+use syn::parse::{Parse, ParseStream, Result};
+use syn::{Attribute, Expr, Ident, Lit, Meta, MetaNameValue, Token, Type};
+
+/// A parameter declared with its options inline, for the function-like macros - e.g.
+/// `#[into] greeting: String = "Hello".to_owned()`
+pub struct InlineParam {
+    pub attrs: Vec<Attribute>,
+    pub name: Ident,
+    pub ty: Type,
+    pub default: Option<Expr>,
+}
+
+impl Parse for InlineParam {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        let default = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(InlineParam { attrs, name, ty, default })
+    }
+}
+
+/// Adds the inline defaults and attributes of the parameters to the options. The attributes can be docs,
+/// the per-parameter options (`into`, `try_into`, `optional`, `nullable`, `overridable`, `validate = ".."`
+/// and `with = ".."`), or anything else - which goes on the parameter's field in the args struct
+pub fn add_to_args(args: &mut Args, params: &[InlineParam]) {
+    for param in params {
+        let name = format!("{}", &param.name);
+        if let Some(default) = &param.default {
+            if args.defaults.contains_key(&name) {
+                panic!("Cannot give '{}' a default inline and in defaults(..) too", name);
+            }
+            args.defaults.insert(name.clone(), DefaultValue::Expr(default.clone()));
+        }
+        for attr in &param.attrs {
+            let meta = attr
+                .interpret_meta()
+                .unwrap_or_else(|| panic!("Not a parseable meta: {}", quote!(#attr)));
+            add_option(args, &name, meta);
+        }
+    }
+}
+
+fn add_option(args: &mut Args, name: &str, meta: Meta) {
+    let option = format!("{}", meta.name());
+    match (option.as_str(), &meta) {
+        ("doc", Meta::NameValue(MetaNameValue { lit: Lit::Str(doc), .. })) => {
+            args.docs.entry(name.to_owned()).or_default().push(doc.value())
+        }
+        ("into", Meta::Word(_)) => {
+            args.into.insert(name.to_owned());
+        }
+        ("try_into", Meta::Word(_)) => {
+            args.try_into.insert(name.to_owned());
+        }
+        ("optional", Meta::Word(_)) => {
+            args.optional.insert(name.to_owned());
+        }
+        ("nullable", Meta::Word(_)) => {
+            args.nullable.insert(name.to_owned());
+        }
+        ("overridable", Meta::Word(_)) => {
+            args.overridable.insert(name.to_owned());
+        }
+        ("validate", Meta::NameValue(MetaNameValue { lit: Lit::Str(expr), .. })) => {
+            args.validate.insert(name.to_owned(), parse_expr(name, expr));
+        }
+        ("with", Meta::NameValue(MetaNameValue { lit: Lit::Str(expr), .. })) => {
+            args.with.insert(name.to_owned(), parse_expr(name, expr));
+        }
+        _ => args.attrs.push(ParsedAttr {
+            location: AttrLocation::Field(name.to_owned()),
+            content: vec![meta],
+        }),
+    }
+}
+
+fn parse_expr(name: &str, lit: &syn::LitStr) -> Expr {
+    lit.parse()
+        .unwrap_or_else(|e| panic!("Cannot parse the expression for '{}' - {}", name, e))
+}
+
+/// The options in a `#[gen_struct_sugar(..)]` attribute among `attrs`, which is taken out of them
+pub fn take_options(attrs: &mut Vec<Attribute>) -> Args {
+    let options = crate::util::drain_map(attrs, |attr| {
+        if attr.path.is_ident("gen_struct_sugar") {
+            Some(attr_args(attr))
+        } else {
+            None
+        }
+    });
+    match options.as_slice() {
+        [] => crate::args::parse_args(TokenStream::new()),
+        [options] => crate::args::parse_args(options.clone()),
+        _ => panic!("Only one gen_struct_sugar(..) attribute can be used per function"),
+    }
+}
+
+/// The arguments of an attribute, without the parentheses around them
+fn attr_args(attr: &Attribute) -> TokenStream {
+    match attr.tts.clone().into_iter().next() {
+        Some(TokenTree::Group(group)) => group.stream(),
+        _ => quote!(),
+    }
+}
@@ -2,15 +2,28 @@
 
 extern crate proc_macro;
 
+mod adapt;
 mod call;
 mod builder;
+mod dyn_builder;
 mod util;
 mod args;
+mod attrs;
+mod constraints;
+mod defaults;
+mod doc;
+mod extern_fn;
+mod ffi;
+mod inline_params;
+mod method;
+mod named_fn;
+mod named_struct;
 mod build;
 mod parse_fn;
+mod validate;
 
 use quote::quote;
-use syn::{parse_macro_input, Item};
+use syn::{parse_macro_input, parse_quote, Item, ItemFn};
 use proc_macro_hack::proc_macro_hack;
 
 #[proc_macro_attribute]
@@ -18,30 +31,52 @@ pub fn gen_struct_sugar(
   args: ::proc_macro::TokenStream,
   input: ::proc_macro::TokenStream,
 ) -> ::proc_macro::TokenStream {
-  let mut args = crate::args::parse_args(args);
+  let input: proc_macro2::TokenStream = input.into();
+  if let Some(block) = block_keyword(&input) {
+    if !args.is_empty() {
+      panic!("Options for the methods of an {} go in the #[gen_struct_sugar(..)] on each method", block);
+    }
+    return expand_block(input).into();
+  }
+  let mut args = crate::args::parse_args(args.into());
+  let (input, param_docs) = doc::strip_param_docs(input);
+  for (name, docs) in param_docs {
+    args.docs.entry(name).or_default().extend(docs);
+  }
+  let input: ::proc_macro::TokenStream = input.into();
 
   let generated_parts = {
-    let mut parsed: Item = parse_macro_input!(input as Item);
-    let structure = match parsed {
-      Item::Fn(ref mut fn_item) => {
-        let structure = parse_fn::parse_field_decl(&mut args, fn_item);
-        build::mod_block_add_defaults(fn_item, &structure);
-        structure
+    let mut parsed: Item = match syn::parse::<Item>(input.clone()) {
+      Ok(parsed) => parsed,
+      Err(err) => {
+        // A method declared in a trait, without a body - its builders are generated by the attribute on the trait
+        if let Ok(syn::TraitItem::Method(_)) = syn::parse::<syn::TraitItem>(input) {
+          panic!(
+            "Trait methods need #[gen_struct_sugar] on their trait too, which generates their builders - the \
+             attribute on the method only gives its options"
+          );
+        }
+        return err.to_compile_error().into();
       }
+    };
+    // A function declared in an extern block gets a wrapper, which is what the rest applies to
+    let mut raw_declaration = quote!();
+    if let Item::ForeignMod(foreign) = parsed {
+      let (wrapper, declaration) = extern_fn::wrap_foreign(foreign, args.safe);
+      parsed = Item::Fn(wrapper);
+      raw_declaration = declaration;
+    } else if args.safe {
+      panic!("safe can only be used on extern blocks");
+    }
+    let fn_item = match parsed {
+      Item::Fn(fn_item) => fn_item,
       _ => {
-        panic!("Can only be used on free-standing fn declarations");
+        panic!("Can only be used on free-standing fn declarations, or extern blocks declaring one function");
       }
     };
 
-    let builder_impl = builder::create_typesafe_builder(&structure);
-
-    let mut generated_parts = vec![builder_impl, quote!(#parsed)];
-
-    if cfg!(feature = "nightly") {
-      let macro_dec = build::gen_macro(&structure);
-      generated_parts.push(macro_dec)
-    }
-
+    let mut generated_parts = vec![raw_declaration];
+    generated_parts.extend(expand_fn(&mut args, fn_item));
     generated_parts
   };
 
@@ -50,6 +85,113 @@ pub fn gen_struct_sugar(
   generated.into()
 }
 
+/// The kind of block the attribute is on, rather than a fn - the first of the keywords is `impl` or `trait`
+fn block_keyword(input: &proc_macro2::TokenStream) -> Option<&'static str> {
+  input.clone().into_iter().find_map(|token| match token {
+    proc_macro2::TokenTree::Ident(ref ident) if ident == "impl" => Some(Some("impl block")),
+    proc_macro2::TokenTree::Ident(ref ident) if ident == "trait" => Some(Some("trait")),
+    proc_macro2::TokenTree::Ident(ref ident) if ident == "fn" || ident == "extern" => Some(None),
+    _ => None,
+  }).unwrap_or(None)
+}
+
+/// Gives the methods of an impl block or trait that are marked with `#[gen_struct_sugar(..)]` named arguments
+fn expand_block(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+  // The parameter docs of every method are stripped from the block's body, before it's parsed
+  let mut tokens: Vec<proc_macro2::TokenTree> = input.into_iter().collect();
+  let mut param_docs = Default::default();
+  if let Some(proc_macro2::TokenTree::Group(body)) = tokens.pop() {
+    let (stripped, docs) = doc::strip_method_param_docs(body.stream());
+    param_docs = docs;
+    let mut stripped = proc_macro2::Group::new(body.delimiter(), stripped);
+    stripped.set_span(body.span());
+    tokens.push(proc_macro2::TokenTree::Group(stripped));
+  }
+  let input: proc_macro2::TokenStream = tokens.into_iter().collect();
+  match syn::parse2::<Item>(input) {
+    Ok(Item::Impl(item_impl)) => method::expand_impl(item_impl, param_docs),
+    Ok(Item::Trait(item_trait)) => method::expand_trait(item_trait, param_docs),
+    Ok(other) => syn::Error::new_spanned(other, "Expected an impl block or a trait").to_compile_error(),
+    Err(err) => err.to_compile_error(),
+  }
+}
+
+/// Generates the args struct, builders and macro for a fn, and rewrites it to fill in the defaults
+fn expand_fn(args: &mut args::Args, fn_item: ItemFn) -> Vec<proc_macro2::TokenStream> {
+  let (structure, fn_item, mut generated_parts) = generate(args, fn_item);
+  generated_parts.push(quote!(#fn_item));
+
+  // The macro would only add to the errors of a clashing name
+  if cfg!(feature = "nightly") && named_struct::name_clash(&structure).is_none() {
+    let macro_dec = build::gen_macro(&structure);
+    generated_parts.push(macro_dec)
+  }
+
+  generated_parts
+}
+
+/// Rewrites the fn to fill in the defaults, and generates the args struct and builders for it - everything but
+/// the macro, which methods don't get
+fn generate(
+  args: &mut args::Args,
+  mut fn_item: ItemFn,
+) -> (parse_fn::Structure, ItemFn, Vec<proc_macro2::TokenStream>) {
+  let mut declared = fn_item.clone();
+  let structure = parse_fn::parse_field_decl(args, &mut fn_item);
+  // Left as declared, bar the attributes read from it, so the clash is the only error
+  if let Some(clash) = named_struct::name_clash(&structure) {
+    declared.attrs = fn_item.attrs;
+    return (structure, declared, vec![clash]);
+  }
+  build::mod_block_add_defaults(&mut fn_item, &structure);
+  let doc = doc::doc_section(&structure);
+  fn_item.attrs.push(parse_quote!(#[doc = #doc]));
+  let output = fn_item.decl.output.clone();
+  let named_struct = named_struct::gen_named_struct(&structure, &mut fn_item);
+
+  let builder_impl = builder::create_typesafe_builder(&structure, &output);
+
+  let dyn_builder_impl = dyn_builder::create_dyn_builder(&structure, &output);
+
+  let overrides_impl = defaults::gen_overrides(&structure);
+
+  let ffi_impl = ffi::gen_ffi(&structure, &output);
+
+  let generated_parts = vec![
+    named_struct,
+    builder_impl,
+    dyn_builder_impl,
+    overrides_impl,
+    ffi_impl,
+  ];
+
+  (structure, fn_item, generated_parts)
+}
+
+/// Gives existing functions (e.g. from `std` or a dependency) a named interface, by declaring them with their
+/// defaults inline - e.g. `adapt! { pub fn std::cmp::max(a: i32, b: i32 = 0) -> i32; }`. Each gets a wrapper
+/// named after the function, in the current module, that forwards to it
+#[proc_macro]
+pub fn adapt(input: ::proc_macro::TokenStream) -> ::proc_macro::TokenStream {
+  let adapted = parse_macro_input!(input as adapt::AdaptedFns);
+  let generated_parts = adapted.fns.into_iter().flat_map(|adapted| {
+    let (mut args, fn_item) = adapted.into_fn();
+    expand_fn(&mut args, fn_item)
+  });
+  quote!(#(#generated_parts)*).into()
+}
+
+/// Declares a fn with its defaults inline, e.g.
+/// `named_fn! { pub fn greet(name: String, *, greeting: String = "Hello".to_owned()) -> String { .. } }`. The
+/// parameters before the `*` are positional, and the same options as for `gen_struct_sugar` can go on the
+/// parameters as attributes (or on the fn in a `#[gen_struct_sugar(..)]`)
+#[proc_macro]
+pub fn named_fn(input: ::proc_macro::TokenStream) -> ::proc_macro::TokenStream {
+  let (mut args, fn_item) = named_fn::parse_named_fn(input.into());
+  let generated_parts = expand_fn(&mut args, fn_item);
+  quote!(#(#generated_parts)*).into()
+}
+
 #[proc_macro_hack]
 pub fn n(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
   call::n(input)
@@ -0,0 +1,433 @@
+use crate::parse_fn::Structure;
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use quote::quote;
+use std::collections::HashMap;
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+use syn::{FnArg, ImplItem, ImplItemMethod, ItemFn, ItemImpl, ItemTrait, MethodSig, ReturnType, TraitItem, Type};
+
+/// How a method takes `self`
+#[derive(Clone, Debug)]
+pub enum Receiver {
+    Ref,
+    RefMut,
+    Value,
+}
+
+/// The type a method is declared on, for a fn that's really a method
+#[derive(Clone, Debug)]
+pub struct Method {
+    pub self_ty: Type,
+    /// The last segment of the type's path, which the names of the generated items start with
+    pub type_name: Ident,
+    pub receiver: Receiver,
+    /// Whether it's declared in a trait, whose path `self_ty` then is - the builders call it on any implementor,
+    /// through the trait's hidden method that fills in the defaults
+    pub in_trait: bool,
+}
+
+impl Method {
+    /// The type of the receiver the builders' `call` takes
+    fn receiver_type(&self) -> TokenStream {
+        let self_ty = &self.self_ty;
+        if self.in_trait {
+            return match self.receiver {
+                Receiver::Ref => quote!(&(impl #self_ty + ?Sized)),
+                Receiver::RefMut => quote!(&mut (impl #self_ty + ?Sized)),
+                Receiver::Value => quote!(impl #self_ty),
+            };
+        }
+        match self.receiver {
+            Receiver::Ref => quote!(&#self_ty),
+            Receiver::RefMut => quote!(&mut #self_ty),
+            Receiver::Value => quote!(#self_ty),
+        }
+    }
+}
+
+/// The hidden method `n!` calls on the receiver, as it can't name the type the builder belongs to
+pub fn trampoline_name(method: &Ident) -> Ident {
+    Ident::new(&format!("__named_{}", method), method.span())
+}
+
+/// The hidden method of a trait that fills in the defaults, which is what the builders call - the declared method
+/// takes every argument, as each implementor implements it
+fn defaults_name(method: &Ident) -> Ident {
+    Ident::new(&format!("__named_{}_defaults", method), method.span())
+}
+
+/// The extra parameter of the builders' `call`, and how it calls the fn with `call_args` - a method is
+/// called on the receiver it's given
+pub fn call(structure: &Structure, call_args: &TokenStream) -> (TokenStream, TokenStream) {
+    let fn_name = &structure.ident;
+    match &structure.method {
+        Some(method) => {
+            let receiver_type = method.receiver_type();
+            let callee = if method.in_trait { defaults_name(fn_name) } else { fn_name.clone() };
+            (quote!(, receiver: #receiver_type), quote!(receiver.#callee(#call_args)))
+        }
+        None => (quote!(), quote!(#fn_name(#call_args))),
+    }
+}
+
+/// Generates the builders for every method in the impl block that's marked with `#[gen_struct_sugar(..)]`.
+/// They can't be declared inside the impl block, so they go after it - along with the block itself, where
+/// each method gets a hidden twin for `n!`, taking a closure that sets up its builder
+pub fn expand_impl(mut item_impl: ItemImpl, mut param_docs: HashMap<String, HashMap<String, Vec<String>>>) -> TokenStream {
+    if let Some((_, ref path, _)) = item_impl.trait_ {
+        panic!(
+            "Cannot use gen_struct_sugar on an impl of '{}' - trait methods have to match the trait, so it goes on \
+             the trait instead, whose named arguments every implementor gets",
+            quote!(#path)
+        );
+    }
+    if !item_impl.generics.params.is_empty() {
+        panic!("Cannot use gen_struct_sugar on a generic impl block - the builders of its methods aren't generic");
+    }
+    let self_ty = (*item_impl.self_ty).clone();
+    let type_name = match self_ty {
+        Type::Path(ref path) if path.qself.is_none() => {
+            let last = path.path.segments.iter().last().unwrap();
+            if !last.arguments.is_empty() {
+                panic!("Cannot use gen_struct_sugar on an impl block for a generic type");
+            }
+            last.ident.clone()
+        }
+        _ => panic!("Can only use gen_struct_sugar on an impl block for a named type, not '{}'", quote!(#self_ty)),
+    };
+
+    let mut generated_parts = vec![];
+    let mut items = vec![];
+    for item in item_impl.items.drain(..) {
+        let mut method = match item {
+            ImplItem::Method(method) => method,
+            other => {
+                items.push(other);
+                continue;
+            }
+        };
+        if !method.attrs.iter().any(|attr| attr.path.is_ident("gen_struct_sugar")) {
+            items.push(ImplItem::Method(method));
+            continue;
+        }
+        let mut args = crate::inline_params::take_options(&mut method.attrs);
+        let docs = param_docs.remove(&format!("{}", &method.sig.ident)).unwrap_or_default();
+        for (name, docs) in docs {
+            args.docs.entry(name).or_default().extend(docs);
+        }
+        let (method, trampoline, parts) = expand_method(&mut args, method, &self_ty, &type_name);
+        items.push(ImplItem::Method(method));
+        items.extend(trampoline.map(ImplItem::Method));
+        generated_parts.extend(parts);
+    }
+    item_impl.items = items;
+
+    quote!(
+        #item_impl
+        #(#generated_parts)*
+    )
+}
+
+/// Generates the builders for every method of the trait that's marked with `#[gen_struct_sugar(..)]`. Each gets
+/// hidden provided methods - one filling in the defaults before calling it, so they're the same for every
+/// implementor, and its twin for `n!`. Both can be called on a trait object, unless the method takes `self`
+pub fn expand_trait(
+    mut item_trait: ItemTrait,
+    mut param_docs: HashMap<String, HashMap<String, Vec<String>>>,
+) -> TokenStream {
+    let trait_name = item_trait.ident.clone();
+    if !item_trait.generics.params.is_empty() || item_trait.generics.where_clause.is_some() {
+        panic!(
+            "Cannot use gen_struct_sugar on the generic trait '{}' - the builders of its methods aren't generic",
+            &trait_name
+        );
+    }
+    let self_ty: Type = syn::parse_quote!(#trait_name);
+
+    let mut generated_parts = vec![];
+    let mut items = vec![];
+    for item in item_trait.items.drain(..) {
+        let mut method = match item {
+            TraitItem::Method(ref method) if method.attrs.iter().any(|attr| attr.path.is_ident("gen_struct_sugar")) => {
+                method.clone()
+            }
+            other => {
+                items.push(other);
+                continue;
+            }
+        };
+        let mut args = crate::inline_params::take_options(&mut method.attrs);
+        let docs = param_docs.remove(&format!("{}", &method.sig.ident)).unwrap_or_default();
+        for (name, docs) in docs {
+            args.docs.entry(name).or_default().extend(docs);
+        }
+        let ident = method.sig.ident.clone();
+        let (receiver_arg, receiver, inputs) = receiver(&args, &method.sig);
+        if !method.sig.decl.generics.params.is_empty() {
+            unsupported(&ident, "generic methods of a trait aren't supported, as their builders aren't generic");
+        }
+        let mentions_self = inputs.iter().any(|input| mentions_self(quote!(#input)))
+            || mentions_self(match method.sig.decl.output {
+                ReturnType::Type(_, ref ty) => quote!(#ty),
+                ReturnType::Default => quote!(),
+            });
+        if mentions_self {
+            unsupported(
+                &ident,
+                "a trait method can't mention `Self` in its parameters or return type, as its builders take any \
+                 implementor",
+            );
+        }
+        let names = inputs.iter().map(|input| match input {
+            FnArg::Captured(syn::ArgCaptured { pat: syn::Pat::Ident(ref pat), .. }) => pat.ident.clone(),
+            other => unsupported(
+                &ident,
+                &format!("the parameter '{}' needs a plain name, as the method is called with it", quote!(#other)),
+            ),
+        }).collect::<Vec<_>>();
+
+        // The fn the args are generated for fills in the defaults and calls the declared method
+        let mut decl = method.sig.decl.clone();
+        decl.inputs = inputs;
+        args.method = Some(Method {
+            self_ty: self_ty.clone(),
+            type_name: trait_name.clone(),
+            receiver: receiver.clone(),
+            in_trait: true,
+        });
+        let fn_item = ItemFn {
+            attrs: method.attrs.clone(),
+            vis: item_trait.vis.clone(),
+            constness: None,
+            unsafety: method.sig.unsafety,
+            asyncness: None,
+            abi: method.sig.abi.clone(),
+            ident: ident.clone(),
+            decl: Box::new(decl),
+            block: Box::new(syn::parse_quote!({ self.#ident(#(#names),*) })),
+        };
+        let (structure, fn_item, parts) = crate::generate(&mut args, fn_item);
+        generated_parts.extend(parts);
+        method.attrs = fn_item.attrs;
+        items.push(TraitItem::Method(method));
+        if crate::named_struct::name_clash(&structure).is_some() {
+            continue;
+        }
+
+        let defaults_name = defaults_name(&ident);
+        let name = trampoline_name(&ident);
+        let args_name = &structure.names.args;
+        let builder_name = &structure.names.builder;
+        let initial_types = crate::builder::initial_types(&structure);
+        let output = &fn_item.decl.output;
+        let output_ty = crate::builder::output_type(&structure, output);
+        let (unsafety, _) = crate::builder::unsafety(&structure);
+        let inputs = &fn_item.decl.inputs;
+        let block = &fn_item.block;
+        let call_args = crate::named_struct::call_args(
+            &structure,
+            structure.names().iter().map(|name| quote!(args.#name)).collect(),
+        );
+        let (receiver, sized) = match receiver {
+            Receiver::Value => (quote!(self), quote!(where Self: Sized)),
+            _ => (quote!(#receiver_arg), quote!()),
+        };
+        items.push(syn::parse2(quote!(
+            #[doc(hidden)]
+            #unsafety fn #defaults_name(#receiver, #inputs) #output #sized #block
+        )).unwrap());
+        items.push(syn::parse2(quote!(
+            #[doc(hidden)]
+            #unsafety fn #name(
+                #receiver,
+                args: Box<dyn FnOnce(#builder_name<#(#initial_types),*>) -> #args_name + '_>,
+            ) -> #output_ty #sized {
+                let args = args(#args_name::builder());
+                self.#defaults_name(#call_args)
+            }
+        )).unwrap());
+    }
+    item_trait.items = items;
+
+    quote!(
+        #item_trait
+        #(#generated_parts)*
+    )
+}
+
+/// Whether the tokens name `Self`
+fn mentions_self(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ref ident) => ident == "Self",
+        TokenTree::Group(ref group) => mentions_self(group.stream()),
+        _ => false,
+    })
+}
+
+/// Rewrites the method to fill in its defaults - with `self` in scope, so they can read it - returning it along
+/// with its twin for `n!` (unless its generated names clash) and the items generated for it
+fn expand_method(
+    args: &mut crate::args::Args,
+    method: ImplItemMethod,
+    self_ty: &Type,
+    type_name: &Ident,
+) -> (ImplItemMethod, Option<ImplItemMethod>, Vec<TokenStream>) {
+    let ident = method.sig.ident.clone();
+    let (receiver_arg, receiver, mut inputs) = receiver(args, &method.sig);
+
+    // The builders are outside the impl block, so `Self` in the signature has to be spelled out
+    for input in inputs.iter_mut() {
+        if let FnArg::Captured(ref mut arg) = input {
+            arg.ty = replace_self(&arg.ty, self_ty);
+        }
+    }
+    let mut decl = method.sig.decl.clone();
+    decl.inputs = inputs;
+    if let ReturnType::Type(_, ref mut ty) = decl.output {
+        **ty = replace_self(ty, self_ty);
+    }
+
+    args.method = Some(Method {
+        self_ty: self_ty.clone(),
+        type_name: type_name.clone(),
+        receiver,
+        in_trait: false,
+    });
+    let fn_item = ItemFn {
+        attrs: method.attrs,
+        vis: method.vis,
+        constness: None,
+        unsafety: method.sig.unsafety,
+        asyncness: None,
+        abi: method.sig.abi,
+        ident: ident.clone(),
+        decl: Box::new(decl),
+        block: Box::new(method.block),
+    };
+    let (structure, fn_item, generated_parts) = crate::generate(args, fn_item);
+
+    let mut decl = *fn_item.decl;
+    decl.inputs.insert(0, receiver_arg.clone());
+    let rewritten = ImplItemMethod {
+        attrs: fn_item.attrs,
+        vis: fn_item.vis,
+        defaultness: method.defaultness,
+        sig: syn::MethodSig {
+            constness: None,
+            unsafety: fn_item.unsafety,
+            asyncness: None,
+            abi: fn_item.abi,
+            ident: ident.clone(),
+            decl,
+        },
+        block: *fn_item.block,
+    };
+
+    if crate::named_struct::name_clash(&structure).is_some() {
+        return (rewritten, None, generated_parts);
+    }
+    let trampoline = {
+        let name = trampoline_name(&ident);
+        let vis = &structure.vis;
+        let args_name = &structure.names.args;
+        let builder_name = &structure.names.builder;
+        let generics = structure.generic_args(false);
+        let type_params = structure.type_params();
+        let initial_types = crate::builder::initial_types(&structure);
+        let output_ty = crate::builder::output_type(&structure, &rewritten.sig.decl.output);
+        let (unsafety, _) = crate::builder::unsafety(&structure);
+        let call_args = crate::named_struct::call_args(
+            &structure,
+            structure.names().iter().map(|name| quote!(args.#name)).collect(),
+        );
+        let receiver = match receiver_arg {
+            FnArg::SelfValue(_) => quote!(self),
+            other => quote!(#other),
+        };
+        syn::parse2(quote!(
+            #[doc(hidden)]
+            #vis #unsafety fn #name<#(#type_params),*>(
+                #receiver,
+                args: Box<dyn FnOnce(#builder_name<#(#initial_types),*>) -> #args_name#generics + '_>,
+            ) -> #output_ty {
+                let args = args(#args_name::builder());
+                self.#ident(#call_args)
+            }
+        )).unwrap()
+    };
+
+    (rewritten, Some(trampoline), generated_parts)
+}
+
+/// Fails on a method that can't have named arguments
+fn unsupported(ident: &Ident, what: &str) -> ! {
+    panic!("Cannot use gen_struct_sugar on '{}' - {}", ident, what)
+}
+
+/// Checks the method can have named arguments, returning its receiver and the rest of its parameters
+fn receiver(args: &crate::args::Args, sig: &MethodSig) -> (FnArg, Receiver, Punctuated<FnArg, Comma>) {
+    let ident = &sig.ident;
+    if sig.constness.is_some() {
+        unsupported(ident, "const methods aren't supported, as the builder of a method can't call it in a const context");
+    }
+    if sig.asyncness.is_some() {
+        unsupported(ident, "async methods aren't supported, as their futures would borrow the receiver given to the builder");
+    }
+    if args.ffi.is_some() {
+        unsupported(ident, "a method can't have an extern \"C\" entry point, as C has no receiver to pass");
+    }
+
+    let receiver_arg = match sig.decl.inputs.iter().next() {
+        Some(arg @ FnArg::SelfRef(_)) | Some(arg @ FnArg::SelfValue(_)) => arg.clone(),
+        _ => unsupported(ident, "associated functions without a `self` parameter aren't supported, only methods"),
+    };
+    let receiver = match receiver_arg {
+        FnArg::SelfRef(ref self_ref) if self_ref.lifetime.is_some() => {
+            unsupported(ident, "the receiver can't have an explicit lifetime, as the builders take it as a plain reference")
+        }
+        FnArg::SelfRef(ref self_ref) if self_ref.mutability.is_some() => Receiver::RefMut,
+        FnArg::SelfRef(_) => Receiver::Ref,
+        _ => Receiver::Value,
+    };
+    (receiver_arg, receiver, sig.decl.inputs.iter().skip(1).cloned().collect())
+}
+
+/// Replaces `Self` in the type with the type the impl block is for
+fn replace_self(ty: &Type, self_ty: &Type) -> Type {
+    fn replace(tokens: TokenStream, self_ty: &Type) -> TokenStream {
+        tokens
+            .into_iter()
+            .flat_map(|token| -> Vec<TokenTree> {
+                match token {
+                    TokenTree::Ident(ref ident) if ident == "Self" => {
+                        let mut replaced = quote!(#self_ty).into_iter().collect::<Vec<_>>();
+                        for token in replaced.iter_mut() {
+                            token.set_span(ident.span());
+                        }
+                        replaced
+                    }
+                    TokenTree::Group(ref group) => {
+                        let mut replaced = proc_macro2::Group::new(group.delimiter(), replace(group.stream(), self_ty));
+                        replaced.set_span(group.span());
+                        vec![TokenTree::Group(replaced)]
+                    }
+                    other => vec![other],
+                }
+            }).collect()
+    }
+    syn::parse2(replace(quote!(#ty), self_ty)).unwrap_or_else(|_| {
+        panic!("Cannot use '{}' in a method with named arguments", quote!(#ty))
+    })
+}
+
+/// The name of a variable holding the type, for example calls in the docs - e.g. `http_client` for `HttpClient`
+pub fn receiver_example(method: &Method) -> String {
+    let mut name = String::new();
+    for (idx, c) in format!("{}", &method.type_name).chars().enumerate() {
+        if c.is_uppercase() && idx > 0 {
+            name.push('_');
+        }
+        name.extend(c.to_lowercase());
+    }
+    name
+}
@@ -0,0 +1,137 @@
+use crate::args::Args;
+use crate::inline_params::InlineParam;
+use crate::named_struct::NamedParam;
+use proc_macro2::{Delimiter, Group, Spacing, TokenStream, TokenTree};
+use quote::quote;
+use syn::parse::{Parse, ParseStream, Parser, Result};
+use syn::punctuated::Punctuated;
+use syn::{braced, token, Attribute, Ident, ItemFn, Token};
+
+/// A parameter of a `named_fn!`, the `*` that separates the positional parameters from the named ones, or a
+/// struct of named parameters (e.g. `named: { .. }`), which the fn then takes instead of each of them
+enum ParamOrMarker {
+    Param(Box<InlineParam>),
+    Marker,
+    Struct(NamedParam, Punctuated<InlineParam, Token![,]>),
+}
+
+impl Parse for ParamOrMarker {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(Token![*]) {
+            input.parse::<Token![*]>()?;
+            Ok(ParamOrMarker::Marker)
+        } else if is_struct(input) {
+            let attrs = input.call(Attribute::parse_outer)?;
+            let name = input.parse()?;
+            input.parse::<Token![:]>()?;
+            let content;
+            braced!(content in input);
+            let fields = content.parse_terminated(InlineParam::parse)?;
+            Ok(ParamOrMarker::Struct(NamedParam { name, attrs }, fields))
+        } else {
+            input.parse().map(|param| ParamOrMarker::Param(Box::new(param)))
+        }
+    }
+}
+
+/// Whether the next parameter is a struct, from its type being braced
+fn is_struct(input: ParseStream) -> bool {
+    let fork = input.fork();
+    fork.call(Attribute::parse_outer).is_ok()
+        && fork.parse::<Ident>().is_ok()
+        && fork.parse::<Token![:]>().is_ok()
+        && fork.peek(token::Brace)
+}
+
+/// Parses a fn declared with its defaults inline, e.g. `fn greet(name: String, *, greeting: String = ..) { .. }`,
+/// into the options and a plain fn - the same as if it had been annotated. The parameters before the `*` are
+/// positional, and any after it are named (as are all of them, without a `*`). The parameters in a struct of named
+/// parameters are named, and the ones before it positional
+pub fn parse_named_fn(input: TokenStream) -> (Args, ItemFn) {
+    let mut params = None;
+    let mut tokens = vec![];
+    let mut seen_fn = false;
+    let mut angle_depth = 0;
+    let mut prev_joint_dash = false;
+    // The parameters are the first parenthesized group after `fn` that isn't in the generics
+    for token in input {
+        match token {
+            TokenTree::Ident(ref ident) if !seen_fn && ident == "fn" => seen_fn = true,
+            TokenTree::Punct(ref punct) if seen_fn && params.is_none() => {
+                match punct.as_char() {
+                    '<' => angle_depth += 1,
+                    '>' if !prev_joint_dash => angle_depth -= 1,
+                    _ => {}
+                }
+                prev_joint_dash = punct.as_char() == '-' && punct.spacing() == Spacing::Joint;
+                tokens.push(token);
+                continue;
+            }
+            TokenTree::Group(ref group)
+                if seen_fn && params.is_none() && angle_depth == 0 && group.delimiter() == Delimiter::Parenthesis =>
+            {
+                let parsed = Punctuated::<ParamOrMarker, Token![,]>::parse_terminated
+                    .parse2(group.stream())
+                    .unwrap_or_else(|e| panic!("Wrong format for the parameters - {}", e));
+                // Filled in once the parameters are plain
+                params = Some((parsed, group.span(), tokens.len()));
+                tokens.push(token);
+                continue;
+            }
+            _ => {}
+        }
+        prev_joint_dash = false;
+        tokens.push(token);
+    }
+    let (parsed, span, index) = params.unwrap_or_else(|| panic!("Expected a fn declaration"));
+
+    let mut params = vec![];
+    let mut positional_count = None;
+    let mut named_param = None;
+    for param in parsed {
+        if named_param.is_some() {
+            panic!("The struct of named parameters has to be the last parameter");
+        }
+        match param {
+            ParamOrMarker::Marker if positional_count.is_some() => {
+                panic!("Only one `*` can separate the positional parameters")
+            }
+            ParamOrMarker::Marker => positional_count = Some(params.len()),
+            ParamOrMarker::Param(param) => params.push(*param),
+            ParamOrMarker::Struct(..) if positional_count.is_some() => {
+                panic!("Cannot use a `*` as well as a struct of named parameters - the parameters before the struct are positional")
+            }
+            ParamOrMarker::Struct(param, fields) => {
+                positional_count = Some(params.len());
+                params.extend(fields);
+                named_param = Some(param);
+            }
+        }
+    }
+    let positional: Vec<String> =
+        params.iter().take(positional_count.unwrap_or(0)).map(|p| format!("{}", &p.name)).collect();
+
+    let names = params.iter().map(|p| &p.name);
+    let types = params.iter().map(|p| &p.ty);
+    let mut plain = Group::new(Delimiter::Parenthesis, quote!(#(#names: #types),*));
+    plain.set_span(span);
+    tokens[index] = TokenTree::Group(plain);
+    let tokens: TokenStream = tokens.into_iter().collect();
+
+    let mut fn_item: ItemFn = syn::parse2(tokens).unwrap_or_else(|e| panic!("Expected a fn declaration - {}", e));
+    let mut args = crate::inline_params::take_options(&mut fn_item.attrs);
+    if !positional.is_empty() || named_param.is_some() {
+        if !args.positional.is_empty() {
+            panic!("Cannot use positionals(..) as well as a `*` or a struct of named parameters");
+        }
+        args.positional = positional;
+    }
+    if named_param.is_some() {
+        if args.named_param.is_some() {
+            panic!("Cannot use named_struct = \"..\" as well as a struct of named parameters");
+        }
+        args.named_param = named_param;
+    }
+    crate::inline_params::add_to_args(&mut args, &params);
+    (args, fn_item)
+}
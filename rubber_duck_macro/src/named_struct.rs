@@ -0,0 +1,139 @@
+use crate::parse_fn::Structure;
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{parse_quote, Attribute, ItemFn};
+
+/// A parameter that takes the named arguments as one struct, e.g. `named: { .. }` in `named_fn!`
+#[derive(Clone, Debug)]
+pub struct NamedParam {
+    pub name: Ident,
+    /// Attributes to put on the generated struct, e.g. derives
+    pub attrs: Vec<Attribute>,
+}
+
+/// The arguments to call the fn with, from the value of each field in order - the named ones go in their struct
+/// if the fn takes them that way
+pub fn call_args(structure: &Structure, values: Vec<TokenStream>) -> TokenStream {
+    if structure.named_param.is_none() {
+        return quote!(#(#values),*);
+    }
+    let named_args = &structure.names.named_args;
+    let positional_count = structure.positional().count();
+    let positional = &values[..positional_count];
+    let named = structure.fields[positional_count..].iter().zip(&values[positional_count..]).map(|(f, value)| {
+        let name = &f.name;
+        quote!(#name: #value)
+    });
+    quote!(#(#positional,)* #named_args { #(#named),* })
+}
+
+/// An error pointing at the custom name of a generated item that's the same as the struct of named parameters,
+/// e.g. `args_name = "GreetArgs"` on a fn `greet` with `named_struct` - they'd be defined twice
+pub fn name_clash(structure: &Structure) -> Option<TokenStream> {
+    structure.named_param.as_ref()?;
+    let named_args = &structure.names.named_args;
+    let names = &structure.names;
+    let custom = [
+        ("args_name", &names.args),
+        ("builder_name", &names.builder),
+        ("dyn_builder_name", &names.dyn_builder),
+    ];
+    let (option, name) = custom
+        .iter()
+        .find(|(_, name)| *name == named_args)
+        .cloned()?;
+    let message = format!(
+        "Cannot use {} = \"{}\" - it's the name of the struct of named parameters of '{}'. Pick another name",
+        option, name, &structure.ident
+    );
+    Some(syn::Error::new(name.span(), message).to_compile_error())
+}
+
+/// Moves the named parameters of the fn into a struct it takes instead, so more can be added without changing
+/// its signature. The fn unpacks the struct before filling in the defaults, so the rest of its body is the same
+pub fn gen_named_struct(structure: &Structure, fn_item: &mut ItemFn) -> TokenStream {
+    let param = match &structure.named_param {
+        Some(param) => param,
+        None => return quote!(),
+    };
+    let named_args = &structure.names.named_args;
+    let vis = &structure.vis;
+    let param_name = &param.name;
+    let attrs = &param.attrs;
+
+    let named: Vec<_> = structure.fields.iter().filter(|f| !f.is_positional()).collect();
+    if named.is_empty() {
+        panic!("Cannot take the named parameters as '{}' - there aren't any, as every parameter is positional", param_name);
+    }
+    if let Some(field) = structure.fields.iter().find(|f| f.is_positional() && &f.name == param_name) {
+        panic!("Cannot name the struct of named parameters '{}' - there's already a parameter with that name", &field.name);
+    }
+    let names: Vec<_> = named.iter().map(|f| &f.name).collect();
+    // Generic over the types of the named `impl Trait` parameters, which the fn then is too
+    let type_params: Vec<_> = named.iter().filter_map(|f| structure.type_param(&f.name)).collect();
+    let generics = if type_params.is_empty() {
+        quote!()
+    } else {
+        let idents = type_params.iter().map(|param| &param.ident);
+        quote!(<#(#idents),*>)
+    };
+    fn_item.decl.generics.params.extend(type_params.iter().map(|param| syn::GenericParam::Type((*param).clone())));
+    let field_decs = named.iter().map(|f| {
+        let name = &f.name;
+        let ty = &f.ty;
+        let docs = &f.docs;
+        let field_attrs = &f.attrs;
+        quote!(
+            #(#[doc = #docs])*
+            #(#[#field_attrs])*
+            #vis #name: #ty,
+        )
+    });
+
+    // The named parameters are the last ones, after the positional ones
+    let positional_count = structure.positional().count();
+    let inputs: Punctuated<_, _> = fn_item.decl.inputs.iter().take(positional_count).cloned().collect();
+    fn_item.decl.inputs = inputs;
+    fn_item.decl.inputs.push(parse_quote!(#param_name: #named_args#generics));
+    let names = &names;
+    let block = fn_item.block.clone();
+    *fn_item.block = parse_quote!({
+        let #named_args { #(#names),* } = #param_name;
+        #block
+    });
+
+    let doc = format!(
+        "The named arguments of {}, which it takes as `{}`. Defaulted arguments are `None` to use their default.",
+        crate::doc::fn_link(structure),
+        param_name
+    );
+    quote!(
+        #[doc = #doc]
+        #(#attrs)*
+        #vis struct #named_args#generics {
+            #(#field_decs)*
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clash(options: &str) -> Option<String> {
+        let mut args = crate::args::parse_args(syn::parse_str(options).unwrap());
+        let mut fn_item: ItemFn = syn::parse_str("fn greet(name: String, greeting: u32) {}").unwrap();
+        let structure = crate::parse_fn::parse_field_decl(&mut args, &mut fn_item);
+        name_clash(&structure).map(|error| error.to_string())
+    }
+
+    #[test]
+    fn rejects_names_of_the_named_struct() {
+        let error = clash(r#"positionals(name), args_name = "GreetArgs", named_struct = "options""#);
+        assert!(error.unwrap().contains("Cannot use args_name = \\\"GreetArgs\\\""));
+        assert!(clash(r#"positionals(name), builder_name = "GreetArgs", named_struct = "options""#).is_some());
+        assert!(clash(r#"positionals(name), args_name = "GreetArgs""#).is_none());
+        assert!(clash(r#"positionals(name), args_name = "Greeting", named_struct = "options""#).is_none());
+    }
+}
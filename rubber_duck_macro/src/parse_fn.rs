@@ -1,15 +1,25 @@
 use crate::args::Args;
+use crate::attrs::AttrLocation;
+use crate::builder::{get_option_type, IsOption};
+use crate::constraints::Constraint;
+use crate::defaults::DefaultValue;
+use crate::method::Method;
+use crate::named_struct::NamedParam;
 use proc_macro2::Ident;
+use proc_macro2::Span;
 use quote::quote;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use syn::parse_quote;
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
 use syn::Expr;
 use syn::FnArg;
 use syn::ItemFn;
+use syn::Meta;
 use syn::Pat;
 use syn::Type;
+use syn::Visibility;
 
 #[derive(Clone)]
 pub enum FieldRole {
@@ -19,21 +29,109 @@ pub enum FieldRole {
 
 #[derive(Clone)]
 pub struct NamedData {
-    pub default: Option<Expr>,
+    pub default: Option<DefaultValue>,
+    /// An `Option` parameter that defaults to `None`, so it isn't wrapped in another `Option`
+    pub optional: bool,
+    /// Whether the default can be overridden for a scope, with `with_defaults`
+    pub overridable: bool,
 }
 
 
 #[derive(Clone)]
 pub struct Field<T> {
     pub name: Ident,
+    /// The type of the parameter, after any wrapping (e.g. in an `Option` for defaults)
     pub ty: Type,
+    /// The type of the parameter as it was declared
+    pub declared_ty: Type,
     pub extra: T,
+    /// Attributes to put on the field of the generated args struct
+    pub attrs: Vec<Meta>,
+    /// The documentation lines for the parameter
+    pub docs: Vec<String>,
+    /// How the builder setter converts its input into the parameter's type
+    pub conversion: Option<Conversion>,
+    /// Whether the (defaulted) parameter is an `Option` itself, so `None` is a value and not a request for the default
+    pub nullable: bool,
+}
+
+#[derive(Clone)]
+pub enum Conversion {
+    /// The setter takes anything that implements `Into<T>`
+    Into,
+    /// The setter takes an `input` and runs `func` on it
+    With { func: Box<Expr>, input: Box<Type> },
+    /// The setter takes anything that implements `TryInto<T>`, failing the call if it can't be converted
+    TryInto,
 }
 
 #[derive(Clone)]
 pub struct Structure {
     pub fields: Vec<Field<FieldRole>>,
     pub ident: Ident,
+    pub names: GeneratedNames,
+    pub vis: Visibility,
+    /// Attributes to put on the generated args struct
+    pub attrs: Vec<Meta>,
+    pub constraints: Vec<Constraint>,
+    /// Checks to run on the arguments before the body is run, by parameter
+    pub validations: Vec<(Ident, Expr)>,
+    /// Converts an `ArgError` into the function's own error type
+    pub map_err: Option<Expr>,
+    /// A config file to read the defaults from when the fn is called
+    pub defaults_file: Option<syn::LitStr>,
+    /// Whether the fn is an `async fn` - its defaults are then made inside the future it returns
+    pub is_async: bool,
+    /// Whether the fn is a `const fn` - its builder is then usable in const contexts too
+    pub is_const: bool,
+    /// Whether the fn is an `unsafe fn` - calling it through the builders is then unsafe too
+    pub is_unsafe: bool,
+    /// Whether to generate an `extern "C"` entry point for the fn - and how
+    pub ffi: Option<crate::ffi::Ffi>,
+    /// The parameter that takes the named arguments as one struct, if they aren't taken one by one
+    pub named_param: Option<NamedParam>,
+    /// The type the fn is a method of - the builders then call it on a receiver they're given
+    pub method: Option<Method>,
+    /// The parameters declared as `impl Trait`, which are type parameters of the generated structs instead
+    pub impl_traits: Vec<ImplTrait>,
+}
+
+/// A parameter declared as `impl Trait` - the generated structs are generic over its type, as `Impl<Name>`
+#[derive(Clone)]
+pub struct ImplTrait {
+    pub field: Ident,
+    /// The type parameter, with the bounds of the `impl Trait`
+    pub param: syn::TypeParam,
+}
+
+/// The identifiers used for the items generated alongside the fn
+#[derive(Clone)]
+pub struct GeneratedNames {
+    /// What the other names are based on - the fn's name, or `Type_method` for a method
+    pub base: Ident,
+    /// The args struct - defaults to the fn name, so `n!` can find it as `fn_name::builder()`
+    pub args: Ident,
+    pub builder: Ident,
+    /// The runtime-checked builder
+    pub dyn_builder: Ident,
+    /// The overrides for the defaults, for `with_defaults`
+    pub overrides: Ident,
+    /// The `#[repr(C)]` args struct, and the `extern "C"` fn taking it
+    pub ffi_args: Ident,
+    pub ffi_fn: Ident,
+    /// The struct of named arguments, for a fn that takes them that way
+    pub named_args: Ident,
+    pub macro_name: Ident,
+    /// Whether the args struct was explicitly named, e.g. it's meant to be part of the api
+    pub custom_args: bool,
+    /// Whether the builder was explicitly named, e.g. it's meant to be part of the api
+    pub custom_builder: bool,
+}
+
+impl<T> Field<T> {
+    pub fn is_try_into(&self) -> bool {
+        matches!(self.conversion, Some(Conversion::TryInto))
+    }
 }
 
 impl Field<FieldRole> {
@@ -44,13 +142,27 @@ impl Field<FieldRole> {
         }
     }
 
-    pub fn default_expr(&self) -> Option<Expr> {
+    pub fn default_value(&self) -> Option<&DefaultValue> {
         match self.extra {
-            FieldRole::Named(ref def) => def.default.clone(),
+            FieldRole::Named(ref def) => def.default.as_ref(),
             FieldRole::Positional => None,
         }
     }
 
+    pub fn is_optional(&self) -> bool {
+        match self.extra {
+            FieldRole::Named(ref def) => def.optional,
+            FieldRole::Positional => false,
+        }
+    }
+
+    pub fn is_overridable(&self) -> bool {
+        match self.extra {
+            FieldRole::Named(ref def) => def.overridable,
+            FieldRole::Positional => false,
+        }
+    }
+
     pub fn is_positional(&self) -> bool {
         match self.extra {
             FieldRole::Named(_) => false,
@@ -61,6 +173,29 @@ impl Field<FieldRole> {
 
 #[allow(dead_code)]
 impl Structure {
+    /// The type parameter of the field, if it's declared as `impl Trait`
+    pub fn type_param(&self, name: &Ident) -> Option<&syn::TypeParam> {
+        self.impl_traits.iter().find(|v| &v.field == name).map(|v| &v.param)
+    }
+
+    /// The type parameters of the generated structs, with their bounds
+    pub fn type_params(&self) -> Vec<&syn::TypeParam> {
+        self.impl_traits.iter().map(|v| &v.param).collect()
+    }
+
+    /// The generic arguments of the generated structs - their type parameters, or `Unset` for each when `unset`,
+    /// for what's reached through the args struct before the types are known, like `builder()`
+    pub fn generic_args(&self, unset: bool) -> proc_macro2::TokenStream {
+        if self.impl_traits.is_empty() {
+            return quote!();
+        }
+        let args = self.impl_traits.iter().map(|v| {
+            let param = &v.param.ident;
+            if unset { quote!(Unset) } else { quote!(#param) }
+        });
+        quote!(<#(#args),*>)
+    }
+
     pub fn names(&self) -> Vec<Ident> {
         self.fields.iter().map(|v| v.name.clone()).collect()
     }
@@ -76,13 +211,24 @@ impl Structure {
         )
     }
 
+    /// Whether the builder needs to track if the field was set, rather than just using the default
+    pub fn is_tracked(&self, field: &Field<FieldRole>) -> bool {
+        !crate::constraints::is_flag(self, &field.name)
+            && self.constraints.iter().any(|c| c.fields().contains(&&field.name))
+    }
+
     pub fn named(&self) -> Vec<Field<NamedData>> {
         self.fields.iter().filter_map(|v| match &v.extra {
             FieldRole::Positional => None,
             FieldRole::Named(ref def) => Some(Field {
                 name: v.name.clone(),
                 ty: v.ty.clone(),
+                declared_ty: v.declared_ty.clone(),
                 extra: def.clone(),
+                attrs: v.attrs.clone(),
+                docs: v.docs.clone(),
+                conversion: v.conversion.clone(),
+                nullable: v.nullable,
             })
         }).collect()
     }
@@ -91,55 +237,322 @@ impl Structure {
 /// Parses the field declaration _and_ modifies signature of any parameters that are optional
 pub fn parse_field_decl(args: &mut Args, fn_item: &mut ItemFn) -> Structure {
     let ident = fn_item.ident.clone();
+    let vis = args.vis.clone().unwrap_or_else(|| fn_item.vis.clone());
+    // Methods of different types can have the same name, so theirs are prefixed with the type
+    let base = match &args.method {
+        Some(method) => Ident::new(&format!("{}_{}", &method.type_name, &ident), ident.span()),
+        None => ident.clone(),
+    };
+    let names = GeneratedNames {
+        args: args.args_name.clone().unwrap_or_else(|| {
+            Ident::new(&crate::util::uppercase(&format!("{}", &base)), base.span())
+        }),
+        builder: args.builder_name.clone().unwrap_or_else(|| {
+            Ident::new(&format!("{}Builder", &base), Span::call_site())
+        }),
+        dyn_builder: args.dyn_builder_name.clone().unwrap_or_else(|| {
+            Ident::new(&format!("{}DynBuilder", &base), Span::call_site())
+        }),
+        overrides: Ident::new(&format!("{}Overrides", &base), Span::call_site()),
+        ffi_args: Ident::new(&format!("{}FfiArgs", &base), Span::call_site()),
+        ffi_fn: Ident::new(&format!("{}_ffi", &base), Span::call_site()),
+        named_args: Ident::new(&format!("{}Args", crate::util::camel_case(&format!("{}", &base))), Span::call_site()),
+        macro_name: args.macro_name.clone().unwrap_or_else(|| base.clone()),
+        base,
+        custom_args: args.args_name.is_some(),
+        custom_builder: args.builder_name.is_some(),
+    };
+    let mut struct_attrs = vec![];
+    let mut field_attrs: HashMap<String, Vec<Meta>> = HashMap::new();
+    let parsed_attrs = args.attrs.drain(..).chain(crate::attrs::parse_attrs(fn_item));
+    for parsed in parsed_attrs {
+        match parsed.location {
+            AttrLocation::Struct => struct_attrs.extend(parsed.content),
+            AttrLocation::Field(name) => field_attrs
+                .entry(name)
+                .or_default()
+                .extend(parsed.content),
+        }
+    }
+
     let decl = &mut fn_item.decl;
-    let unvalidated_fields = parse_fields(&mut decl.inputs, &mut args.defaults);
+    let unvalidated_fields = parse_fields(&mut decl.inputs, &mut args.defaults, &args.try_into, &mut args.optional);
 
     let mut positional = vec![];
     let mut named = vec![];
     let mut positional_iter = args.positional.iter();
 
-    for unvalidated in unvalidated_fields {
+    let mut impl_traits = vec![];
+
+    for mut unvalidated in unvalidated_fields {
         let name_string = format!("{}", unvalidated.name);
+        // A top level `impl Trait` becomes a type parameter of the generated structs - nested ones can't
+        let impl_trait = match unvalidated.declared_ty {
+            Type::ImplTrait(ref impl_trait) => Some(impl_trait.clone()),
+            ref ty if is_impl_trait(ty) => panic!(
+                "Cannot take '{}' as `{}` - only a parameter declared as `impl Trait` itself can be generic, not one \
+                 with an `impl Trait` inside its type. Declare it with a concrete type, or as a whole `impl Trait`",
+                name_string,
+                crate::doc::pretty(ty)
+            ),
+            _ => None,
+        };
+        if let Some(ref impl_trait) = impl_trait {
+            let generic = |what: &str| -> ! {
+                panic!(
+                    "Cannot {} '{}' - it's declared as `{}`, so its type is only known once it's set",
+                    what,
+                    name_string,
+                    crate::doc::pretty(impl_trait)
+                )
+            };
+            if unvalidated.optional {
+                generic("make optional");
+            }
+            if unvalidated.default.is_some() {
+                generic("give a default to");
+            }
+            if args.into.contains(&name_string) || args.with.contains_key(&name_string) || args.try_into.contains(&name_string) {
+                generic("add a conversion to");
+            }
+            if args.overridable.contains(&name_string) {
+                generic("make overridable");
+            }
+            let param = Ident::new(&format!("Impl{}", crate::util::camel_case(&name_string)), unvalidated.name.span());
+            let bounds = &impl_trait.bounds;
+            unvalidated.ty = parse_quote!(#param);
+            impl_traits.push(ImplTrait {
+                field: unvalidated.name.clone(),
+                param: parse_quote!(#param: #bounds),
+            });
+        }
+        let attrs = field_attrs.remove(&name_string).unwrap_or_default();
+        let docs = args.docs.remove(&name_string).unwrap_or_default();
+        let try_into = args.try_into.remove(&name_string);
+        let conversion = match (args.with.remove(&name_string), args.into.remove(&name_string)) {
+            (Some(_), true) => panic!("Cannot use both into and with for '{}'", name_string),
+            (_, _) if try_into && unvalidated.optional => {
+                panic!("Cannot use try_into with optional for '{}'", name_string)
+            }
+            (_, true) | (Some(_), _) if try_into => {
+                panic!("Cannot use try_into with into or with for '{}'", name_string)
+            }
+            (Some(with), false) => Some(parse_with(with)),
+            (None, true) => Some(Conversion::Into),
+            (None, false) if try_into => Some(Conversion::TryInto),
+            (None, false) if args.into_all => Some(Conversion::Into),
+            (None, false) => None,
+        };
+        let nullable = match (args.nullable.remove(&name_string), &unvalidated.default) {
+            (true, None) => panic!(
+                "Cannot make '{}' nullable - only parameters with defaults can be",
+                name_string
+            ),
+            (true, Some(_)) if unvalidated.optional => panic!(
+                "Cannot make '{}' both optional and nullable - optional parameters default to None",
+                name_string
+            ),
+            (true, Some(_)) => true,
+            (false, Some(_)) if unvalidated.optional => false,
+            (false, Some(_)) => match get_option_type(unvalidated.declared_ty.clone()) {
+                IsOption::True(_) => true,
+                IsOption::False => false,
+            },
+            (false, None) => false,
+        };
+        let overridable = args.overridable.remove(&name_string);
+        if overridable && (unvalidated.default.is_none() || unvalidated.optional) {
+            panic!(
+                "Cannot make '{}' overridable - only parameters with a declared default can be",
+                name_string
+            );
+        }
         if let Some(next_positional_name) = positional_iter.next() {
             if &name_string != next_positional_name {
                 panic!(
                     "The positional field list must be in the same order as the declared fields"
                 );
             }
+            if unvalidated.optional {
+                panic!("Cannot make '{}' optional - positional fields can't be left out", name_string);
+            }
             if unvalidated.default.is_some() {
                 panic!("Cannot set a default value for a positional field");
             }
             positional.push(Field {
                 name: unvalidated.name,
                 ty: unvalidated.ty,
-                extra: FieldRole::Positional
+                declared_ty: unvalidated.declared_ty,
+                extra: FieldRole::Positional,
+                attrs,
+                docs,
+                conversion,
+                nullable,
             })
         } else {
             named.push(Field {
                 name: unvalidated.name,
                 ty: unvalidated.ty,
-                extra: FieldRole::Named(NamedData { default: unvalidated.default}),
+                declared_ty: unvalidated.declared_ty,
+                extra: FieldRole::Named(NamedData {
+                    default: unvalidated.default,
+                    optional: unvalidated.optional,
+                    overridable,
+                }),
+                attrs,
+                docs,
+                conversion,
+                nullable,
             })
         }
     }
 
-    let fields = positional.into_iter().chain(named.into_iter()).collect();
+    let fields: Vec<Field<FieldRole>> = positional.into_iter().chain(named.into_iter()).collect();
+    let validations = fields
+        .iter()
+        .filter_map(|f| {
+            args.validate
+                .remove(&format!("{}", f.name))
+                .map(|check| (f.name.clone(), check))
+        }).collect();
+
+    if let Some(name) = field_attrs.keys().next() {
+        panic!("Cannot add attributes to '{}' - there's no parameter with that name", name);
+    }
+    let mut conversions = args.with.keys().chain(args.into.iter()).chain(args.try_into.iter());
+    if let Some(name) = conversions.next() {
+        panic!("Cannot add a conversion to '{}' - there's no parameter with that name", name);
+    }
+    if let Some(name) = args.overridable.iter().next() {
+        panic!("Cannot make '{}' overridable - there's no parameter with that name", name);
+    }
+    if let Some(name) = args.optional.iter().next() {
+        panic!("Cannot make '{}' optional - there's no parameter with that name", name);
+    }
+    if let Some(name) = args.nullable.iter().next() {
+        panic!("Cannot make '{}' nullable - there's no parameter with that name", name);
+    }
+    if let Some(name) = args.validate.keys().next() {
+        panic!("Cannot validate '{}' - there's no parameter with that name", name);
+    }
+    if let Some(name) = args.docs.keys().next() {
+        panic!("Cannot add docs to '{}' - there's no parameter with that name", name);
+    }
 
-    Structure {
+    let structure = Structure {
         fields,
         ident,
+        names,
+        vis,
+        attrs: struct_attrs,
+        constraints: args.constraints.clone(),
+        validations,
+        map_err: args.map_err.clone(),
+        defaults_file: args.defaults_file.clone(),
+        is_async: fn_item.asyncness.is_some(),
+        is_const: fn_item.constness.is_some(),
+        is_unsafe: fn_item.unsafety.is_some(),
+        ffi: args.ffi.clone(),
+        named_param: args.named_param.clone(),
+        method: args.method.clone(),
+        impl_traits,
+    };
+    crate::constraints::validate(&structure);
+    for field in structure.fields.iter().filter(|f| f.has_default()) {
+        let maybe_setter = crate::builder::maybe_setter_name(field);
+        if structure.fields.iter().any(|f| f.name == maybe_setter) {
+            panic!(
+                "Cannot name a parameter '{}' - its setter would clash with the maybe_ setter of '{}', which has a default",
+                maybe_setter, &field.name
+            );
+        }
+    }
+    if let (true, Some(field)) = (structure.is_async, structure.fields.iter().find(|f| f.is_overridable())) {
+        panic!(
+            "Cannot make '{}' overridable - overrides are thread local, and the future of an async fn can be polled \
+             outside of `with_defaults`",
+            &field.name
+        );
+    }
+    if let (true, Some(impl_trait)) = (structure.is_const, structure.impl_traits.first()) {
+        panic!(
+            "Cannot take '{}' as `impl Trait` in a const fn - its builder can't be const over its type",
+            &impl_trait.field
+        );
+    }
+    if structure.is_const {
+        check_const(&structure);
+    }
+    structure
+}
+
+/// Everything the builder of a `const fn` does has to be const too - so nothing that goes through traits,
+/// closures or statics, or is looked up when called
+fn check_const(structure: &Structure) {
+    let unsupported = |what: &str| panic!("Cannot use {} on a const fn, as it can't be done in a const context", what);
+    if let Some(field) = structure.fields.iter().find(|f| f.conversion.is_some()) {
+        unsupported(&format!("a conversion for '{}'", &field.name));
+    }
+    if let Some(field) = structure.fields.iter().find(|f| crate::defaults::is_made_when_called(structure, f)) {
+        unsupported(&format!("the default of '{}'", &field.name));
+    }
+    if !structure.validations.is_empty() {
+        unsupported("validate(..)");
+    }
+    if !structure.constraints.is_empty() {
+        unsupported("argument constraints");
+    }
+}
+
+/// Whether the type is (or contains) an `impl Trait`, which can't be the type of a struct field
+fn is_impl_trait(ty: &Type) -> bool {
+    fn has_impl(tokens: proc_macro2::TokenStream) -> bool {
+        tokens.into_iter().any(|token| match token {
+            proc_macro2::TokenTree::Ident(ref ident) => ident == "impl",
+            proc_macro2::TokenTree::Group(ref group) => has_impl(group.stream()),
+            _ => false,
+        })
+    }
+    has_impl(quote!(#ty))
+}
+
+/// `with` takes either `func: InputType` or a closure with a typed parameter, as the setter needs the input type
+fn parse_with(expr: Expr) -> Conversion {
+    match expr {
+        Expr::Type(expr_type) => Conversion::With {
+            func: expr_type.expr,
+            input: expr_type.ty,
+        },
+        Expr::Closure(closure) => {
+            let input = match closure.inputs.iter().collect::<Vec<_>>().as_slice() {
+                [FnArg::Captured(arg)] => Box::new(arg.ty.clone()),
+                _ => panic!("A conversion closure needs exactly one parameter, with its type declared"),
+            };
+            Conversion::With {
+                func: Box::new(Expr::Closure(closure)),
+                input,
+            }
+        }
+        _ => panic!(
+            "Expected a conversion in the form of \"function: InputType\" or \"|input: InputType| ..\", got '{}'",
+            quote!(#expr)
+        ),
     }
 }
 
 struct UnvalidatedField {
     pub name: Ident,
     pub ty: Type,
-    pub default: Option<Expr>,
+    pub declared_ty: Type,
+    pub default: Option<DefaultValue>,
+    pub optional: bool,
 }
 
 fn parse_fields(
     args: &mut Punctuated<FnArg, Comma>,
-    defaults: &mut HashMap<String, Expr>,
+    defaults: &mut HashMap<String, DefaultValue>,
+    try_into: &HashSet<String>,
+    optional: &mut HashSet<String>,
 ) -> Vec<UnvalidatedField> {
     args.iter_mut()
         .map(|arg| match arg {
@@ -155,7 +568,33 @@ fn parse_fields(
                         panic!("Subpattern not supported yet for argument")
                     }
 
-                    let default = defaults.remove(&format!("{}", &pat.ident));
+                    let name = format!("{}", &pat.ident);
+                    let declared_ty = arg.ty.clone();
+
+                    // An optional parameter's `None` is its default, so its type is left as is
+                    if optional.remove(&name) {
+                        if defaults.contains_key(&name) {
+                            panic!("Cannot set a default value for optional parameter '{}' - it defaults to None", name);
+                        }
+                        if let IsOption::False = get_option_type(declared_ty.clone()) {
+                            panic!("Cannot make '{}' optional - its type needs to be an Option", name);
+                        }
+                        return UnvalidatedField {
+                            name: pat.ident.clone(),
+                            ty: declared_ty.clone(),
+                            declared_ty,
+                            default: Some(DefaultValue::Expr(parse_quote!(None))),
+                            optional: true,
+                        };
+                    }
+
+                    let default = defaults.remove(&name);
+
+                    // The conversion error is carried into the fn, which bails out before running the body
+                    if try_into.contains(&name) {
+                        let ty = arg.ty.clone();
+                        arg.ty = parse_quote!(Result<#ty, crate::ArgError>);
+                    }
 
                     if default.is_some() {
                         let new_ty = {
@@ -169,7 +608,9 @@ fn parse_fields(
                     UnvalidatedField {
                         name: pat.ident.clone(),
                         ty: arg.ty.clone(),
+                        declared_ty,
                         default,
+                        optional: false,
                     }
                 }
                 _ => panic!(
@@ -177,6 +618,12 @@ fn parse_fields(
                     arg
                 ),
             },
+            // A method's args struct and builders can't be declared inside its impl block (or trait), so they're
+            // generated by the attribute on the block instead
+            FnArg::SelfRef(_) | FnArg::SelfValue(_) => panic!(
+                "Methods need #[gen_struct_sugar] on their impl block or trait too, which generates their builders - \
+                 the attribute on the method only gives its options"
+            ),
             _ => panic!("Unsupported fn arg type!"),
         }).collect()
 }
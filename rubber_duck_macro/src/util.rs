@@ -1,4 +1,3 @@
-#[allow(dead_code)] // Will be used once field & struct annotation piggy-backing is implemented
 pub fn drain_map<I, O>(input: &mut Vec<I>, filter_map: impl Fn(&mut I) -> Option<O>) -> Vec<O> {
     let mut ret = vec![];
     let mut i = 0;
@@ -13,6 +12,18 @@ pub fn drain_map<I, O>(input: &mut Vec<I>, filter_map: impl Fn(&mut I) -> Option
     ret
 }
 
+/// Writes a snake case name in camel case, for the names of generated types - e.g. `DoSomething` for `do_something`
+pub fn camel_case(s: &str) -> String {
+    s.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }).collect()
+}
+
 // Probably should use some external crate for this...
 pub fn uppercase(s: &str) -> String {
     //    let mut c = s.chars();
@@ -0,0 +1,143 @@
+use crate::parse_fn::Structure;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{ItemFn, PathArguments, ReturnType, Type};
+
+/// What sort of `Result` the fn returns, if any
+enum Returns {
+    /// A `Result<T, E>`, which validation errors can be converted into with `From`
+    Result,
+    /// Something named `Result` without an error type, e.g. `io::Result<T>` - its error type can't be named
+    ResultAlias,
+    Option,
+    Other,
+}
+
+/// Goes by the last segment of the path of the return type
+fn returns(fn_item: &ItemFn) -> Returns {
+    let ty = match fn_item.decl.output {
+        ReturnType::Type(_, ref ty) => ty,
+        ReturnType::Default => return Returns::Other,
+    };
+    let segment = match **ty {
+        Type::Path(ref path) => match path.path.segments.iter().last() {
+            Some(segment) => segment,
+            None => return Returns::Other,
+        },
+        _ => return Returns::Other,
+    };
+    if segment.ident == "Option" {
+        return Returns::Option;
+    }
+    if segment.ident != "Result" {
+        return Returns::Other;
+    }
+    match segment.arguments {
+        PathArguments::AngleBracketed(ref generics) if generics.args.len() == 2 => Returns::Result,
+        _ => Returns::ResultAlias,
+    }
+}
+
+/// `try` defaults are evaluated in the fn body, so their `?` needs the fn to return a `Result` or an `Option`
+pub fn check_try_defaults(fn_item: &ItemFn, structure: &Structure) {
+    let field = match structure.fields.iter().find(|f| crate::defaults::is_try(f)) {
+        Some(field) => field,
+        None => return,
+    };
+    if let Returns::Other = returns(fn_item) {
+        panic!(
+            "Cannot use a try default for '{}' - its `?` needs the function to return a Result or an Option",
+            &field.name
+        );
+    }
+}
+
+pub fn has_checks(structure: &Structure) -> bool {
+    first_check(structure).is_some()
+}
+
+/// Describes the first thing that can reject the arguments with an `ArgError`, for error messages
+fn first_check(structure: &Structure) -> Option<String> {
+    if let Some((name, _)) = structure.validations.first() {
+        return Some(format!("the validate(..) check on '{}'", name));
+    }
+    if let Some(constraint) = structure.constraints.first() {
+        return Some(format!("the constraint {}", constraint.describe()));
+    }
+    if let Some(field) = structure.fields.iter().find(|f| f.is_try_into()) {
+        return Some(format!("the try_into(..) conversion of '{}'", &field.name));
+    }
+    structure
+        .fields
+        .iter()
+        .find(|f| crate::defaults::is_runtime(structure, f))
+        .map(|field| format!("the default of '{}', looked up when called,", &field.name))
+}
+
+/// The statements that unwrap the `try_into` conversions and run the validations, returning
+/// early with an `ArgError` if either fails - so the fn has to return a `Result`. The second value
+/// is what converts an `ArgError` into the fn's error, for any other early returns
+pub fn gen_checks(fn_item: &ItemFn, structure: &Structure) -> (TokenStream, Option<TokenStream>) {
+    if !has_checks(structure) {
+        if structure.map_err.is_some() {
+            panic!(
+                "map_err needs validate(..), try_into(..), constraints or defaults looked up when called to be used"
+            );
+        }
+        return (quote!(), None);
+    }
+
+    let map_err = match (returns(fn_item), &structure.map_err) {
+        (Returns::Result, None) => quote!(::std::convert::From::from),
+        (Returns::Result, Some(map_err)) | (Returns::ResultAlias, Some(map_err)) => quote!(#map_err),
+        (Returns::ResultAlias, None) => panic!(
+            "Cannot tell which error type '{}' returns, to convert argument errors into - use map_err = \"..\", \
+             or return a Result<T, E>",
+            &structure.ident
+        ),
+        (Returns::Option, _) | (Returns::Other, _) => panic!(
+            "{} returns an ArgError when it fails, so '{}' needs to return a Result, e.g. Result<T, ArgError> - \
+             validate(..), try_into(..), constraints and defaults looked up when called all do",
+            first_check(structure).expect("has checks"),
+            &structure.ident
+        ),
+    };
+
+    let conversions = structure.fields.iter().filter(|f| f.is_try_into()).map(|f| {
+        let name = &f.name;
+        quote!(
+            let #name = match #name {
+                ::std::result::Result::Ok(__value) => __value,
+                ::std::result::Result::Err(__err) => return ::std::result::Result::Err((#map_err)(__err)),
+            };
+        )
+    });
+
+    let validations = structure.validations.iter().map(|(name, check)| {
+        let param = format!("{}", name);
+        let reason = format!("failed the check `{}`", crate::doc::pretty(check));
+        quote!(
+            if !(#check) {
+                return ::std::result::Result::Err((#map_err)(crate::ArgError::new(#param, #reason)));
+            }
+        )
+    });
+
+    (quote!(#(#conversions)* #(#validations)*), Some(map_err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "the constraint exclusive(append, truncate) returns an ArgError when it fails, so 'open' needs to return a Result")]
+    fn names_the_check_that_needs_a_result() {
+        let mut args = crate::args::parse_args(
+            syn::parse_str(r#"defaults(append = "false", truncate = "false"), exclusive(append, truncate)"#).unwrap(),
+        );
+        let mut fn_item: ItemFn = syn::parse_str("fn open(append: bool, truncate: bool) {}").unwrap();
+        let structure = crate::parse_fn::parse_field_decl(&mut args, &mut fn_item);
+        gen_checks(&fn_item, &structure);
+    }
+}